@@ -0,0 +1,215 @@
+//! 停止中のサーバを起動できるプレイヤー
+//!
+//! 設定ファイルに書いた名前と UUID に加えて、サーバの `whitelist.json` を読める。
+//! `whitelist.json` は更新日時が変わるたびに読み直すので、サーバ側で
+//! `/whitelist add` した結果がそのまま反映される。
+//!
+//! 照合する名前と UUID は Login Start でクライアントが名乗ったもので、認証 (online-mode の
+//! 暗号化) の前なので偽れる。allowlist にある名前を入力すれば誰でも起動できるので、
+//! うっかり起動されるのを防ぐ程度のものとして使う。判定は [`AuditEntry`] で記録する。
+
+use std::{
+    collections::HashSet,
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::config::AllowlistConfig;
+
+/// `whitelist.json` の要素
+#[derive(Deserialize, Debug, Clone)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+pub fn read_whitelist(path: &Path) -> anyhow::Result<Vec<WhitelistEntry>> {
+    let s = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&s)?)
+}
+
+/// 読み込んだ `whitelist.json` と、そのときの更新日時
+#[derive(Debug, Clone)]
+pub struct Whitelist {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    entries: Vec<WhitelistEntry>,
+}
+
+impl Whitelist {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            entries: read_whitelist(path)?,
+        })
+    }
+
+    /// 更新されていれば読み直す。読めなければ前回の内容のまま使う。
+    fn sync(&mut self) {
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+
+        match read_whitelist(&self.path) {
+            Ok(entries) => {
+                self.entries = entries;
+                self.modified = modified;
+            }
+            Err(e) => eprintln!("{} を読み込めませんでした: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+pub struct Allowlist {
+    /// 小文字にしたもの
+    names: HashSet<String>,
+    uuids: HashSet<Uuid>,
+    whitelist: Option<Mutex<Whitelist>>,
+}
+
+impl Allowlist {
+    /// `whitelist.json` は `Config::load` で読み込んだものを使う
+    pub fn new(config: &AllowlistConfig) -> Self {
+        Self {
+            names: config.names.iter().map(|n| n.to_lowercase()).collect(),
+            uuids: config.uuids.iter().copied().collect(),
+            whitelist: config.loaded_whitelist.clone().map(Mutex::new),
+        }
+    }
+
+    /// 名前 (大文字小文字は区別しない) か UUID のどちらかが一致すれば許可する
+    pub fn allows(&self, name: &str, uuid: Option<Uuid>) -> bool {
+        let name = name.to_lowercase();
+        if self.names.contains(&name) || uuid.is_some_and(|uuid| self.uuids.contains(&uuid)) {
+            return true;
+        }
+
+        let Some(whitelist) = &self.whitelist else {
+            return false;
+        };
+        let mut whitelist = whitelist.lock().unwrap();
+        whitelist.sync();
+        whitelist
+            .entries
+            .iter()
+            .any(|entry| entry.name.to_lowercase() == name || Some(entry.uuid) == uuid)
+    }
+}
+
+/// allowlist で判定した結果の監査ログ
+///
+/// 1 行の logfmt 形式で、名前は名乗ったままなのでエスケープして引用符で囲む。
+///
+/// `2026-10-18T09:00:00Z allowlist server="survival" result=denied player="Steve" uuid=- peer=203.0.113.5:51234`
+pub struct AuditEntry<'a> {
+    pub at: SystemTime,
+    pub server: &'a str,
+    pub allowed: bool,
+    pub name: &'a str,
+    pub uuid: Option<Uuid>,
+    /// 接続してきたアドレス。名前と違って偽れない。
+    pub peer: SocketAddr,
+}
+
+impl fmt::Display for AuditEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allowlist server={:?} result={} player={:?} uuid={} peer={}",
+            rfc3339(self.at),
+            self.server,
+            if self.allowed { "allowed" } else { "denied" },
+            self.name,
+            self.uuid
+                .map_or_else(|| "-".to_string(), |uuid| uuid.to_string()),
+            self.peer
+        )
+    }
+}
+
+/// `2026-10-18T09:00:00Z` (UTC、秒まで)
+fn rfc3339(at: SystemTime) -> String {
+    let secs = at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, time) = ((secs / 86400) as i64, secs % 86400);
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn allows_names_and_uuids() {
+        let uuid: Uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5".parse().unwrap();
+        let allowlist = Allowlist::new(&AllowlistConfig {
+            names: vec!["Steve".to_string()],
+            uuids: vec![uuid],
+            ..Default::default()
+        });
+
+        assert!(allowlist.allows("steve", None));
+        assert!(allowlist.allows("Notch", Some(uuid)));
+        assert!(!allowlist.allows("Alex", None));
+        assert!(!allowlist.allows("Alex", Some(Uuid::nil())));
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(rfc3339(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(at(951868799)), "2000-02-29T23:59:59Z");
+        assert_eq!(rfc3339(at(1760745600 + 3723)), "2025-10-18T01:02:03Z");
+    }
+
+    #[test]
+    fn writes_one_escaped_line() {
+        let entry = AuditEntry {
+            at: UNIX_EPOCH,
+            server: "survival",
+            allowed: false,
+            name: "Steve\nforged=1",
+            uuid: None,
+            peer: "203.0.113.5:51234".parse().unwrap(),
+        };
+        assert_eq!(
+            entry.to_string(),
+            r#"1970-01-01T00:00:00Z allowlist server="survival" result=denied player="Steve\nforged=1" uuid=- peer=203.0.113.5:51234"#
+        );
+    }
+}
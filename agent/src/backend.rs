@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+
+pub mod ec2;
+pub mod mock;
+pub mod process;
+
+pub use ec2::Ec2Backend;
+pub use mock::MockBackend;
+pub use process::ProcessBackend;
+
+/// ホスト側から見たサーバの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendState {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+    Unknown,
+}
+
+/// Minecraft サーバを動かしているホストの操作
+#[async_trait]
+pub trait Backend: Send + Sync {
+    async fn start(&self) -> anyhow::Result<()>;
+    async fn stop(&self) -> anyhow::Result<()>;
+    async fn state(&self) -> anyhow::Result<BackendState>;
+
+    /// プロキシの転送先 (`host:port`)
+    async fn address(&self) -> anyhow::Result<String>;
+}
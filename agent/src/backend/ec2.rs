@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use aws_config::{meta::region::RegionProviderChain, BehaviorVersion, Region};
+use aws_sdk_ec2::{
+    types::{Instance, InstanceStateName},
+    Client,
+};
+use tokio::sync::OnceCell;
+
+use super::{Backend, BackendState};
+
+pub const DEFAULT_REGION: &str = "ap-northeast-1";
+pub const DEFAULT_PORT: u16 = 25565;
+
+pub struct Ec2Backend {
+    instance_id: String,
+    region: String,
+    address: Option<String>,
+    port: u16,
+    client: OnceCell<Client>,
+}
+
+impl Ec2Backend {
+    pub fn new(instance_id: &str) -> Self {
+        Self {
+            instance_id: instance_id.to_string(),
+            region: DEFAULT_REGION.to_string(),
+            address: None,
+            port: DEFAULT_PORT,
+            client: OnceCell::new(),
+        }
+    }
+
+    /// 環境変数などからリージョンを決められなかったときに使うリージョン
+    pub fn with_region(mut self, region: &str) -> Self {
+        self.region = region.to_string();
+        self
+    }
+
+    /// 転送先を固定する。指定しなければインスタンスの IP アドレスを使う。
+    pub fn with_address(mut self, address: &str) -> Self {
+        self.address = Some(address.to_string());
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    async fn client(&self) -> &Client {
+        self.client
+            .get_or_init(|| async {
+                let region_provider = RegionProviderChain::default_provider()
+                    .or_else(Region::new(self.region.clone()));
+                let config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+                    .region(region_provider)
+                    .load()
+                    .await;
+                Client::new(&config)
+            })
+            .await
+    }
+
+    async fn describe(&self) -> anyhow::Result<Instance> {
+        let res = self
+            .client()
+            .await
+            .describe_instances()
+            .instance_ids(&self.instance_id)
+            .send()
+            .await?;
+
+        res.reservations()
+            .iter()
+            .flat_map(|reservation| reservation.instances())
+            .next()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Instance not found: {}", self.instance_id))
+    }
+}
+
+#[async_trait]
+impl Backend for Ec2Backend {
+    async fn start(&self) -> anyhow::Result<()> {
+        let _start_instances_response = self
+            .client()
+            .await
+            .start_instances()
+            .instance_ids(&self.instance_id)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        let _stop_instances_response = self
+            .client()
+            .await
+            .stop_instances()
+            .instance_ids(&self.instance_id)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn state(&self) -> anyhow::Result<BackendState> {
+        let instance = self.describe().await?;
+
+        let state = match instance.state().and_then(|state| state.name()) {
+            Some(InstanceStateName::Pending) => BackendState::Starting,
+            Some(InstanceStateName::Running) => BackendState::Running,
+            Some(InstanceStateName::Stopping | InstanceStateName::ShuttingDown) => {
+                BackendState::Stopping
+            }
+            Some(InstanceStateName::Stopped | InstanceStateName::Terminated) => {
+                BackendState::Stopped
+            }
+            _ => BackendState::Unknown,
+        };
+
+        Ok(state)
+    }
+
+    async fn address(&self) -> anyhow::Result<String> {
+        if let Some(address) = &self.address {
+            return Ok(address.clone());
+        }
+
+        let instance = self.describe().await?;
+        let ip = instance
+            .public_ip_address()
+            .or(instance.private_ip_address())
+            .ok_or_else(|| anyhow::anyhow!("Instance has no IP address: {}", self.instance_id))?;
+
+        Ok(format!("{}:{}", ip, self.port))
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex,
+};
+
+use async_trait::async_trait;
+
+use super::{Backend, BackendState};
+
+/// AWS などを使わずに動作を確かめるためのバックエンド
+///
+/// `start`/`stop` は状態を書き換えるだけなので、転送先には別途サーバ
+/// (`server` バイナリなど) を立てておく。
+pub struct MockBackend {
+    state: Mutex<BackendState>,
+    address: String,
+    fail: AtomicBool,
+    slow_stop: AtomicBool,
+    start_count: AtomicUsize,
+    stop_count: AtomicUsize,
+}
+
+impl MockBackend {
+    pub fn new(address: &str) -> Self {
+        Self {
+            state: Mutex::new(BackendState::Stopped),
+            address: address.to_string(),
+            fail: AtomicBool::new(false),
+            slow_stop: AtomicBool::new(false),
+            start_count: AtomicUsize::new(0),
+            stop_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_state(&self, state: BackendState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    /// `true` にすると `start`/`stop` がエラーを返す
+    pub fn set_fail(&self, fail: bool) {
+        self.fail.store(fail, Ordering::SeqCst);
+    }
+
+    /// `true` にすると `stop` の後も `Stopping` のままになる (EC2 の stopping の代わり)。
+    /// `set_state` で `Stopped` にするまで止まらない。
+    pub fn set_slow_stop(&self, slow_stop: bool) {
+        self.slow_stop.store(slow_stop, Ordering::SeqCst);
+    }
+
+    pub fn start_count(&self) -> usize {
+        self.start_count.load(Ordering::SeqCst)
+    }
+
+    pub fn stop_count(&self) -> usize {
+        self.stop_count.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Backend for MockBackend {
+    async fn start(&self) -> anyhow::Result<()> {
+        self.start_count.fetch_add(1, Ordering::SeqCst);
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(anyhow::anyhow!("Mock backend failed to start"));
+        }
+
+        self.set_state(BackendState::Running);
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        self.stop_count.fetch_add(1, Ordering::SeqCst);
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(anyhow::anyhow!("Mock backend failed to stop"));
+        }
+
+        if self.slow_stop.load(Ordering::SeqCst) {
+            self.set_state(BackendState::Stopping);
+        } else {
+            self.set_state(BackendState::Stopped);
+        }
+        Ok(())
+    }
+
+    async fn state(&self) -> anyhow::Result<BackendState> {
+        Ok(*self.state.lock().unwrap())
+    }
+
+    async fn address(&self) -> anyhow::Result<String> {
+        Ok(self.address.clone())
+    }
+}
@@ -0,0 +1,109 @@
+use std::{path::PathBuf, process::Stdio, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, Command},
+    sync::Mutex,
+    time,
+};
+
+use super::{Backend, BackendState};
+
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 同じホスト上でサーバのプロセスを起動・停止する
+pub struct ProcessBackend {
+    program: String,
+    args: Vec<String>,
+    directory: Option<PathBuf>,
+    address: String,
+    child: Mutex<Option<Child>>,
+}
+
+impl ProcessBackend {
+    pub fn new(
+        command: &[String],
+        directory: Option<PathBuf>,
+        address: &str,
+    ) -> anyhow::Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+
+        Ok(Self {
+            program: program.clone(),
+            args: args.to_vec(),
+            directory,
+            address: address.to_string(),
+            child: Mutex::new(None),
+        })
+    }
+}
+
+#[async_trait]
+impl Backend for ProcessBackend {
+    async fn start(&self) -> anyhow::Result<()> {
+        let mut child = self.child.lock().await;
+        if let Some(child) = child.as_mut() {
+            if child.try_wait()?.is_none() {
+                return Ok(());
+            }
+        }
+
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(directory) = &self.directory {
+            command.current_dir(directory);
+        }
+
+        *child = Some(command.spawn()?);
+
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        // 終了を回収するまでは手放さない。失敗したら `state` は Running のまま。
+        let mut guard = self.child.lock().await;
+        let Some(child) = guard.as_mut() else {
+            return Ok(());
+        };
+
+        // RCON の stop などで既に終わっていなければ、サーバコンソールに stop を送って、
+        // 終わらなければ kill する
+        if child.try_wait()?.is_none() {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(b"stop\n").await?;
+                stdin.flush().await?;
+            }
+
+            if time::timeout(STOP_TIMEOUT, child.wait()).await.is_err() {
+                child.kill().await?;
+            }
+        }
+
+        *guard = None;
+        Ok(())
+    }
+
+    async fn state(&self) -> anyhow::Result<BackendState> {
+        let mut child = self.child.lock().await;
+        let is_running = match child.as_mut() {
+            Some(child) => child.try_wait()?.is_none(),
+            None => false,
+        };
+
+        Ok(if is_running {
+            BackendState::Running
+        } else {
+            BackendState::Stopped
+        })
+    }
+
+    async fn address(&self) -> anyhow::Result<String> {
+        Ok(self.address.clone())
+    }
+}
@@ -1,25 +1,649 @@
-use std::env;
+use std::{
+    env,
+    io::{stdout, IsTerminal},
+    net::IpAddr,
+    path::PathBuf,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
-use agent::minecraft;
+use agent::minecraft::{
+    self,
+    bedrock::{self, Bedrock, BedrockStatus},
+    client::{self, Client},
+    connection::split_address,
+    dns::Resolver,
+    packet::status_response::StatusResponse,
+    query::{FullStat, Query},
+    raw_json_text::{ColorDepth, RawJsonText},
+    version,
+};
+use serde_json::Value;
 
-fn main() -> anyhow::Result<()> {
-    let args = env::args().skip(1).collect::<Vec<_>>();
+const USAGE: &str = "Usage: [--legacy] [--no-color] [--json | --format TEMPLATE | --nagios] [--warn-latency MS] [--crit-latency MS] [--warn-players N] [--crit-players N] [--protocol VERSION] [--resolver IP:PORT] [--save-favicon PATH] [--query | --bedrock] [HOST[:PORT]] (PORT)";
 
-    if args.is_empty() || 2 < args.len() {
-        println!("Usage: [HOST] (PORT)");
-        return Ok(());
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+enum Output {
+    Pretty,
+    Json,
+    /// `{players.online}/{players.max}` のように JSON のパスを埋め込む
+    Format(String),
+    /// Nagios/Icinga のプラグインとして、結果を終了コードで返す
+    Nagios,
+}
+
+/// これ以上になったら WARNING / CRITICAL
+#[derive(Default)]
+struct Thresholds {
+    warn_latency: Option<u128>,
+    crit_latency: Option<u128>,
+    warn_players: Option<u128>,
+    crit_players: Option<u128>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<ExitCode> {
+    // Nagios は終了コード 1 を WARNING と読むので、引数の誤りなども含めて
+    // エラーは必ず自分で UNKNOWN (繋がらなければ CRITICAL) として返す
+    let nagios = env::args().skip(1).any(|arg| arg == "--nagios");
+
+    match run().await {
+        Err(e) if nagios => Ok(nagios_error(&e)),
+        result => result,
+    }
+}
+
+async fn run() -> anyhow::Result<ExitCode> {
+    let mut args = env::args().skip(1);
+    let mut positional = vec![];
+    let mut resolver = None;
+    let mut legacy = false;
+    let mut protocol = version::LATEST;
+    let mut color = stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let mut output = Output::Pretty;
+    let mut thresholds = Thresholds::default();
+    let mut save_favicon: Option<PathBuf> = None;
+    let mut use_query = false;
+    let mut use_bedrock = false;
+
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for {}\n{}", flag, USAGE))
+        };
+
+        match flag {
+            "--legacy" => legacy = true,
+            "--query" => use_query = true,
+            "--bedrock" => use_bedrock = true,
+            "--no-color" => color = false,
+            "--json" => output = Output::Json,
+            "--format" => output = Output::Format(value()?),
+            "--nagios" => output = Output::Nagios,
+            "--warn-latency" => thresholds.warn_latency = Some(value()?.parse()?),
+            "--crit-latency" => thresholds.crit_latency = Some(value()?.parse()?),
+            "--warn-players" => thresholds.warn_players = Some(value()?.parse()?),
+            "--crit-players" => thresholds.crit_players = Some(value()?.parse()?),
+            "--protocol" => {
+                let protocol_arg = value()?;
+                protocol = version::parse(&protocol_arg)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown version: {}", protocol_arg))?;
+            }
+            "--resolver" => resolver = Some(Resolver::new(value()?.parse()?)),
+            "--save-favicon" => save_favicon = Some(value()?.into()),
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.is_empty() || 2 < positional.len() {
+        if let Output::Nagios = output {
+            return Err(anyhow::anyhow!("Expected HOST[:PORT] [PORT]"));
+        }
+        println!("{}", USAGE);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let address = positional[0].as_str();
+    let port = positional.get(1).map(|port| port.parse()).transpose()?;
+    let result = if use_bedrock {
+        bedrock_status(address, port)
+            .await
+            .map(|(status, latency)| (Response::Bedrock(status), latency))
+    } else if use_query {
+        query_full_stat(address, port, resolver.as_ref())
+            .await
+            .map(|(stat, latency)| (Response::Query(stat), latency))
+    } else {
+        query(address, port, resolver.as_ref(), protocol, legacy)
+            .await
+            .map(|(status, latency)| (Response::Status(status), latency))
+    };
+
+    let (response, latency) = result?;
+
+    if let Some(path) = &save_favicon {
+        let favicon = match &response {
+            Response::Status(status) => status.favicon.as_ref(),
+            Response::Query(_) | Response::Bedrock(_) => None,
+        };
+        let favicon = favicon.ok_or_else(|| anyhow::anyhow!("Server did not return a favicon"))?;
+        std::fs::write(path, favicon.png())?;
+    }
+
+    let depth = color.then(ColorDepth::detect);
+    match output {
+        Output::Pretty => match &response {
+            Response::Status(status) => print_status(status, latency, depth),
+            Response::Query(stat) => print_full_stat(stat, latency, depth),
+            Response::Bedrock(status) => print_bedrock_status(status, latency, depth),
+        },
+        Output::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&response.report(latency))?
+        ),
+        Output::Format(template) => {
+            println!("{}", format(&template, &response.report(latency)))
+        }
+        Output::Nagios => {
+            let (online, max) = response.players();
+            return Ok(nagios(online, max, latency, &thresholds));
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// 表示する応答。`--query` なら UDP の Query、`--bedrock` なら RakNet の Pong で得たもの。
+enum Response {
+    Status(StatusResponse),
+    Query(FullStat),
+    Bedrock(BedrockStatus),
+}
+
+impl Response {
+    fn players(&self) -> (usize, usize) {
+        match self {
+            Response::Status(status) => (status.players.online, status.players.max),
+            Response::Query(stat) => (stat.num_players, stat.max_players),
+            Response::Bedrock(status) => (status.online_players, status.max_players),
+        }
+    }
+
+    /// 応答そのままに、装飾を除いた `motd` と `latency_ms` を足したもの
+    fn report(&self, latency: Option<Duration>) -> Value {
+        let (mut report, motd) = match self {
+            Response::Status(status) => (
+                serde_json::to_value(status).unwrap_or_default(),
+                normalize(&status.description),
+            ),
+            Response::Query(stat) => (
+                serde_json::to_value(stat).unwrap_or_default(),
+                RawJsonText::from_legacy(&stat.motd),
+            ),
+            Response::Bedrock(status) => (
+                serde_json::to_value(status).unwrap_or_default(),
+                RawJsonText::from_legacy(&status.motd),
+            ),
+        };
+
+        if let Value::Object(map) = &mut report {
+            map.insert("motd".to_string(), Value::String(motd.to_plain()));
+            map.insert(
+                "latency_ms".to_string(),
+                latency.map_or(Value::Null, |latency| {
+                    Value::from(latency.as_millis() as u64)
+                }),
+            );
+        }
+
+        report
+    }
+}
+
+/// `--resolver` が無ければ resolv.conf の nameserver を使う。
+/// ポートを指定したときは SRV を引かないので、resolv.conf が無くても動くように必要になってから読む。
+///
+/// 読めなくてもサーバが落ちているわけではないので、接続のエラーとは区別する。
+fn srv_resolver(resolver: Option<&Resolver>) -> anyhow::Result<Resolver> {
+    match resolver {
+        Some(resolver) => Ok(resolver.clone()),
+        None => Resolver::system()
+            .map_err(|e| anyhow::anyhow!("Could not read the system resolver: {}", e)),
+    }
+}
+
+async fn query(
+    address: &str,
+    port: Option<u16>,
+    resolver: Option<&Resolver>,
+    protocol: i32,
+    legacy: bool,
+) -> anyhow::Result<(StatusResponse, Option<Duration>)> {
+    let connect = || async {
+        anyhow::Ok(match port {
+            Some(port) => Client::new(address, port).await?,
+            None => Client::connect(address, &srv_resolver(resolver)?).await?,
+        })
+    };
+
+    if !legacy {
+        let mut client = connect().await?.with_protocol(protocol);
+        match client.status().await {
+            Ok(status) => {
+                let latency = client.ping().await?;
+                return Ok((status, Some(latency)));
+            }
+            // 1.7 より前のサーバは新しいハンドシェイクを理解できない
+            Err(e) => eprintln!("Falling back to legacy ping: {}", e),
+        }
+    }
+
+    let status = connect().await?.legacy_status().await?;
+    Ok((status, None))
+}
+
+/// Query のポートは server.properties の `query.port` で、既定ではゲームと同じ
+async fn query_full_stat(
+    address: &str,
+    port: Option<u16>,
+    resolver: Option<&Resolver>,
+) -> anyhow::Result<(FullStat, Option<Duration>)> {
+    let (host, address_port) =
+        split_address(address).ok_or_else(|| anyhow::anyhow!("Invalid address: {}", address))?;
+    let (host, port) = match port.or(address_port) {
+        Some(port) => (host.to_string(), port),
+        None => {
+            // `Client::connect` と同じく、引けなければ SRV なしとして扱う
+            let srv = match host.parse::<IpAddr>() {
+                Ok(_) => None,
+                Err(_) => srv_resolver(resolver)?
+                    .lookup_minecraft(host)
+                    .await
+                    .ok()
+                    .flatten(),
+            };
+            srv.unwrap_or_else(|| (host.to_string(), client::DEFAULT_PORT))
+        }
+    };
+
+    let mut query = Query::connect(&host, port).await?;
+    let started = Instant::now();
+    let stat = query.full_stat().await?;
+
+    Ok((stat, Some(started.elapsed())))
+}
+
+/// Bedrock には SRV レコードが無いので、ポートを省略したら 19132
+async fn bedrock_status(
+    address: &str,
+    port: Option<u16>,
+) -> anyhow::Result<(BedrockStatus, Option<Duration>)> {
+    let (host, port) = match (port, split_address(address)) {
+        (Some(port), _) => (address, port),
+        (None, Some((host, port))) => (host, port.unwrap_or(bedrock::DEFAULT_PORT)),
+        (None, None) => (address, bedrock::DEFAULT_PORT),
+    };
+
+    let mut bedrock = Bedrock::connect(host, port).await?;
+    let started = Instant::now();
+    let status = bedrock.status().await?;
+
+    Ok((status, Some(started.elapsed())))
+}
+
+/// 文字列のまま `§` の書式コードを使っているサーバも多い
+fn normalize(text: &RawJsonText) -> RawJsonText {
+    match text {
+        RawJsonText::String(s) => RawJsonText::from_legacy(s),
+        text => text.clone(),
+    }
+}
+
+fn render(text: &RawJsonText, depth: Option<ColorDepth>) -> String {
+    let text = normalize(text);
+    match depth {
+        Some(depth) => text.to_ansi(depth),
+        None => text.to_plain(),
+    }
+}
+
+fn print_status(status: &StatusResponse, latency: Option<Duration>, depth: Option<ColorDepth>) {
+    let row = |label: &str, value: &str| {
+        for (i, line) in value.lines().enumerate() {
+            let label = if i == 0 { label } else { "" };
+            match depth {
+                Some(_) => println!("{}{:<9}{} {}", BOLD, label, RESET, line),
+                None => println!("{:<9} {}", label, line),
+            }
+        }
+    };
+
+    row("MOTD", &render(&status.description, depth));
+
+    let protocol = match version::name(status.version.protocol) {
+        Some(name) => format!("{}, {}", status.version.protocol, name),
+        None => status.version.protocol.to_string(),
+    };
+    let name = render(&RawJsonText::from(status.version.name.as_str()), depth);
+    row("Version", &format!("{} ({})", name, protocol));
+
+    let players = &status.players;
+    row("Players", &format!("{} / {}", players.online, players.max));
+    for player in players.sample.iter().flatten() {
+        let name = render(&RawJsonText::from(player.name.as_str()), depth);
+        row("", &format!("  {}  {}", name, player.id));
+    }
+
+    if let Some((width, height)) = status.favicon.as_ref().and_then(|f| f.dimensions()) {
+        row("Favicon", &format!("{}x{} PNG", width, height));
+    }
+
+    if let Some(latency) = latency {
+        row("Latency", &format!("{} ms", latency.as_millis()));
+    }
+}
+
+fn print_full_stat(stat: &FullStat, latency: Option<Duration>, depth: Option<ColorDepth>) {
+    let row = |label: &str, value: &str| match depth {
+        Some(_) => println!("{}{:<9}{} {}", BOLD, label, RESET, value),
+        None => println!("{:<9} {}", label, value),
+    };
+
+    for (i, line) in render(&RawJsonText::from_legacy(&stat.motd), depth)
+        .lines()
+        .enumerate()
+    {
+        row(if i == 0 { "MOTD" } else { "" }, line);
+    }
+    row("Version", &stat.version);
+    row("Map", &stat.map);
+
+    let (software, plugins) = stat.plugin_list();
+    if let Some(software) = software {
+        row("Software", software);
+    }
+    for (i, plugin) in plugins.iter().enumerate() {
+        row(if i == 0 { "Plugins" } else { "" }, plugin);
+    }
+
+    row(
+        "Players",
+        &format!("{} / {}", stat.num_players, stat.max_players),
+    );
+    for player in &stat.players {
+        row("", &format!("  {}", player));
+    }
+
+    if let Some(latency) = latency {
+        row("Latency", &format!("{} ms", latency.as_millis()));
+    }
+}
+
+fn print_bedrock_status(
+    status: &BedrockStatus,
+    latency: Option<Duration>,
+    depth: Option<ColorDepth>,
+) {
+    let row = |label: &str, value: &str| match depth {
+        Some(_) => println!("{}{:<9}{} {}", BOLD, label, RESET, value),
+        None => println!("{:<9} {}", label, value),
+    };
+
+    row(
+        "MOTD",
+        &render(&RawJsonText::from_legacy(&status.motd), depth),
+    );
+    if !status.sub_motd.is_empty() {
+        row(
+            "",
+            &render(&RawJsonText::from_legacy(&status.sub_motd), depth),
+        );
+    }
+    row(
+        "Version",
+        &format!(
+            "{} ({}, {})",
+            status.version, status.protocol, status.edition
+        ),
+    );
+    if !status.game_mode.is_empty() {
+        row("Mode", &status.game_mode);
+    }
+    row(
+        "Players",
+        &format!("{} / {}", status.online_players, status.max_players),
+    );
+
+    if let Some(latency) = latency {
+        row("Latency", &format!("{} ms", latency.as_millis()));
+    }
+}
+
+/// `{a.b.0}` を JSON の値に置き換える。見つからなければ空文字列。
+fn format(template: &str, report: &Value) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+
+        let path = &rest[start + 1..start + len];
+        let value = path.split('.').try_fold(report, |value, key| match value {
+            Value::Array(values) => key.parse::<usize>().ok().and_then(|i| values.get(i)),
+            value => value.get(key),
+        });
+        match value {
+            Some(Value::String(s)) => out.push_str(s),
+            Some(Value::Null) | None => {}
+            Some(value) => out.push_str(&value.to_string()),
+        }
+
+        rest = &rest[start + len + 1..];
     }
+    out.push_str(rest);
 
-    let host = args[0].as_str();
-    let port: u16 = if args.len() == 2 {
-        args[1].parse()?
+    // シェルから渡しやすいように
+    out.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+const NAGIOS_OK: u8 = 0;
+const NAGIOS_WARNING: u8 = 1;
+const NAGIOS_CRITICAL: u8 = 2;
+const NAGIOS_UNKNOWN: u8 = 3;
+
+fn nagios_label(code: u8) -> &'static str {
+    match code {
+        NAGIOS_OK => "OK",
+        NAGIOS_WARNING => "WARNING",
+        NAGIOS_CRITICAL => "CRITICAL",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 値がしきい値以上ならその重さ
+fn nagios_level(value: u128, warn: Option<u128>, crit: Option<u128>) -> u8 {
+    if crit.is_some_and(|crit| crit <= value) {
+        NAGIOS_CRITICAL
+    } else if warn.is_some_and(|warn| warn <= value) {
+        NAGIOS_WARNING
     } else {
-        25565
+        NAGIOS_OK
+    }
+}
+
+fn nagios(
+    online: usize,
+    max: usize,
+    latency: Option<Duration>,
+    thresholds: &Thresholds,
+) -> ExitCode {
+    let threshold = |value: Option<u128>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    let mut code = nagios_level(
+        online as u128,
+        thresholds.warn_players,
+        thresholds.crit_players,
+    );
+    let mut summary = format!("{}/{} players", online, max);
+    let mut perfdata = format!(
+        "players={};{};{};0;{}",
+        online,
+        threshold(thresholds.warn_players),
+        threshold(thresholds.crit_players),
+        max
+    );
+
+    if let Some(latency) = latency {
+        let ms = latency.as_millis();
+        code = code.max(nagios_level(
+            ms,
+            thresholds.warn_latency,
+            thresholds.crit_latency,
+        ));
+        summary.push_str(&format!(", {} ms", ms));
+        perfdata.push_str(&format!(
+            " latency={}ms;{};{};0;",
+            ms,
+            threshold(thresholds.warn_latency),
+            threshold(thresholds.crit_latency)
+        ));
+    }
+
+    println!(
+        "MINECRAFT {} - {} | {}",
+        nagios_label(code),
+        summary,
+        perfdata
+    );
+    ExitCode::from(code)
+}
+
+/// 繋がらなければ CRITICAL、引数の誤りや繋がったが話が通じなかったときは UNKNOWN
+fn nagios_error(e: &anyhow::Error) -> ExitCode {
+    let code = match e.downcast_ref::<minecraft::Error>() {
+        Some(e) if e.is_unreachable() => NAGIOS_CRITICAL,
+        _ => NAGIOS_UNKNOWN,
     };
 
-    let mut client = minecraft::client::Client::new(host, port)?;
-    let status = client.status()?;
-    println!("{:#?}", status);
+    println!("MINECRAFT {} - {}", nagios_label(code), e);
+    ExitCode::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io;
 
-    Ok(())
+    fn report() -> Value {
+        json!({
+            "version": { "name": "Paper 1.21.1", "protocol": 767 },
+            "players": {
+                "max": 20,
+                "online": 2,
+                "sample": [{ "name": "Steve" }, { "name": "Alex" }],
+            },
+            "motd": "A Minecraft Server",
+            "latency_ms": null,
+        })
+    }
+
+    #[test]
+    fn format_fills_in_paths() {
+        let report = report();
+        assert_eq!(
+            format("{players.online}/{players.max} {version.name}", &report),
+            "2/20 Paper 1.21.1"
+        );
+        assert_eq!(format("{players.sample.1.name}", &report), "Alex");
+        assert_eq!(
+            format("{version}", &report),
+            r#"{"name":"Paper 1.21.1","protocol":767}"#
+        );
+        assert_eq!(format("{motd}\\n", &report), "A Minecraft Server\n");
+    }
+
+    #[test]
+    fn format_leaves_missing_values_empty() {
+        let report = report();
+        assert_eq!(format("[{favicon}]", &report), "[]");
+        assert_eq!(format("[{latency_ms}]", &report), "[]");
+        assert_eq!(format("[{players.sample.5.name}]", &report), "[]");
+        assert_eq!(format("[{players.sample.x}]", &report), "[]");
+        assert_eq!(format("[{motd.text}]", &report), "[]");
+    }
+
+    #[test]
+    fn format_keeps_unclosed_brace() {
+        let report = report();
+        assert_eq!(format("{players.online} {players", &report), "2 {players");
+        assert_eq!(format("}{", &report), "}{");
+    }
+
+    #[test]
+    fn nagios_level_checks_critical_first() {
+        assert_eq!(nagios_level(99, Some(100), Some(200)), NAGIOS_OK);
+        assert_eq!(nagios_level(100, Some(100), Some(200)), NAGIOS_WARNING);
+        assert_eq!(nagios_level(200, Some(100), Some(200)), NAGIOS_CRITICAL);
+        // しきい値の順序が逆でも重い方を返す
+        assert_eq!(nagios_level(150, Some(200), Some(100)), NAGIOS_CRITICAL);
+        assert_eq!(nagios_level(150, None, Some(200)), NAGIOS_OK);
+        assert_eq!(nagios_level(u128::MAX, None, None), NAGIOS_OK);
+    }
+
+    #[test]
+    fn nagios_takes_the_worst_level() {
+        let thresholds = Thresholds {
+            warn_latency: Some(100),
+            crit_latency: Some(500),
+            warn_players: Some(10),
+            crit_players: Some(20),
+        };
+        let latency = |ms| Some(Duration::from_millis(ms));
+
+        assert_eq!(
+            nagios(2, 20, latency(10), &thresholds),
+            ExitCode::from(NAGIOS_OK)
+        );
+        assert_eq!(
+            nagios(10, 20, latency(10), &thresholds),
+            ExitCode::from(NAGIOS_WARNING)
+        );
+        assert_eq!(
+            nagios(10, 20, latency(500), &thresholds),
+            ExitCode::from(NAGIOS_CRITICAL)
+        );
+        assert_eq!(
+            nagios(20, 20, None, &thresholds),
+            ExitCode::from(NAGIOS_CRITICAL)
+        );
+    }
+
+    #[test]
+    fn nagios_error_distinguishes_unreachable() {
+        let error = |e: minecraft::Error| nagios_error(&e.into());
+
+        assert_eq!(
+            error(io::Error::from(io::ErrorKind::ConnectionRefused).into()),
+            ExitCode::from(NAGIOS_CRITICAL)
+        );
+        assert_eq!(
+            error(minecraft::Error::Timeout),
+            ExitCode::from(NAGIOS_CRITICAL)
+        );
+        assert_eq!(
+            error(minecraft::Error::Protocol("malformed packet".to_string())),
+            ExitCode::from(NAGIOS_UNKNOWN)
+        );
+        assert_eq!(
+            nagios_error(&anyhow::anyhow!("Invalid argument")),
+            ExitCode::from(NAGIOS_UNKNOWN)
+        );
+    }
 }
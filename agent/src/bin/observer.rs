@@ -1,9 +1,10 @@
 use anyhow::Result;
-use std::{thread, time::Duration};
+use std::time::Duration;
 
-use agent::minecraft::client;
+use agent::minecraft::{self, client};
 
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     let servers: Vec<(&str, u16)> = vec![("localhost", 25565)];
 
     let mut unused_count = vec![0_usize; servers.len()];
@@ -12,16 +13,36 @@ fn main() -> Result<()> {
         let handles: Vec<_> = servers
             .iter()
             .map(|&(host, port)| {
-                thread::spawn(move || -> Result<usize> {
-                    let mut client = client::Client::new(host, port)?;
-                    let count = client.get_online_players_count()?;
-                    Ok(count)
+                tokio::spawn(async move {
+                    let mut client = client::Client::new(host, port).await?;
+                    let count = client.get_online_players_count().await?;
+                    let latency = client.ping().await?;
+                    Ok::<_, minecraft::Error>((count, latency))
                 })
             })
             .collect();
 
         for (i, handle) in handles.into_iter().enumerate() {
-            let count = handle.join().unwrap().unwrap_or(0);
+            let count = match handle.await? {
+                Ok((count, latency)) => {
+                    println!(
+                        "{}:{} responded in {} ms",
+                        servers[i].0,
+                        servers[i].1,
+                        latency.as_millis()
+                    );
+                    count
+                }
+                // 落ちているサーバには誰もいない
+                Err(e) if e.is_unreachable() => 0,
+                Err(e) => {
+                    eprintln!(
+                        "{}:{} is not a compatible server: {}",
+                        servers[i].0, servers[i].1, e
+                    );
+                    continue;
+                }
+            };
 
             if count == 0 {
                 unused_count[i] += 1;
@@ -33,6 +54,6 @@ fn main() -> Result<()> {
             println!("{}:{} -> {}", servers[i].0, servers[i].1, unused_count[i]);
         }
 
-        thread::sleep(Duration::from_secs(5));
+        tokio::time::sleep(Duration::from_secs(5)).await;
     }
 }
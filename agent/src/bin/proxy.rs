@@ -1,63 +1,153 @@
+use agent::allowlist::{Allowlist, AuditEntry};
+use agent::backend::{Backend, BackendState};
+use agent::config::{self, BedrockConfig, Config, RconConfig, ServerConfig};
+use agent::lifecycle::{Lifecycle, State};
+use agent::limbo::Limbo;
 use agent::minecraft::{
-    self, client,
+    bedrock::{self, BedrockStatus},
+    client,
+    connection::{split_address, Connection},
+    favicon::Favicon,
+    legacy,
     packet::{
         disconnect_login::DisconnectLogin,
         handshake::Handshake,
-        ping::Ping,
-        read_packet,
-        status_request::StatusRequest,
-        status_response::{self, Players, Version},
-        WritePacketExt,
+        login_start::LoginStart,
+        ping::{Ping, Pong},
+        status_response::{Players, StatusResponse, Version},
     },
-    raw_json_text::RawJsonText,
+    query::{self, ChallengeTokens, FullStat, Query, Request},
+    raw_json_text::{Color, Object, RawJsonText},
+    rcon::Rcon,
+    version,
 };
-use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
-use aws_sdk_ec2::Client;
 use std::{
+    collections::HashMap,
     env,
+    future::Future,
+    net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
-    thread,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 use tokio::{
-    net::{TcpListener, TcpStream},
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::OnceCell,
     time, try_join,
 };
 
-const USAGE_CHECK_INTERVAL: time::Duration = time::Duration::from_secs(60 * 5);
+const DEFAULT_CONFIG_PATH: &str = "proxy.toml";
+const BOOT_CHECK_INTERVAL: Duration = Duration::from_secs(20);
+const LIMBO_TICK_INTERVAL: Duration = Duration::from_secs(1);
+const SERVER_STOP_TIMEOUT: Duration = Duration::from_secs(60);
+const BACKEND_STOP_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// EC2 の stopping は数分かかることがある
+const BACKEND_STOP_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+/// サーバのチャレンジトークンが変わるまでは同じ中継を使う。
+/// RakNet のセッションもこれより短い間隔でパケットをやり取りする。
+const UDP_RELAY_TIMEOUT: Duration = Duration::from_secs(30);
+/// 中継ごとにソケットを 1 つ使うので、送信元を偽ったパケットでファイル記述子を
+/// 使い切られないように数を抑える
+const MAX_UDP_RELAYS: usize = 256;
+/// Bedrock の中継はプレイヤーのセッションそのもので長く続くので、Query とは別に数える。
+/// 認証前の RakNet パケットで作られるため、上限はサーバの定員程度にする。
+const MAX_BEDROCK_RELAYS: usize = 128;
+/// ファイル記述子が尽きたときなどに accept をやり直すまでの間隔
+const ACCEPT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// チャット欄とアクションバーの両方に表示する
+async fn broadcast(rcon: &mut Rcon, text: &RawJsonText) -> anyhow::Result<()> {
+    rcon.command(&format!("say {}", text.to_plain())).await?;
+    rcon.command(&format!(
+        "title @a actionbar {}",
+        serde_json::to_string(text)?
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// 起動に失敗したときなどの文言を目立たせる
+fn alert(text: RawJsonText) -> RawJsonText {
+    Object::text("").color(Color::Red).extra(text).into()
+}
 
 struct Server {
-    is_proxy: Arc<Mutex<bool>>,
-    server_address: String,
-    client_address: String,
-    instance_id: String,
+    lifecycle: Arc<Lifecycle>,
+    config: Arc<ServerConfig>,
+    backend: Arc<dyn Backend>,
+    favicon: Option<Favicon>,
+    allowlist: Option<Arc<Allowlist>>,
 }
 
 impl Server {
-    pub fn new(client_address: &str, server_address: &str, instance_id: &str) -> Self {
-        Self {
-            is_proxy: Arc::new(Mutex::new(false)),
-            server_address: server_address.to_string(),
-            client_address: client_address.to_string(),
-            instance_id: instance_id.to_string(),
-        }
+    pub fn new(config: ServerConfig) -> anyhow::Result<Self> {
+        let backend = config.backend()?;
+        let favicon = config.loaded_favicon.clone();
+        let allowlist = config.allowlist().map(Arc::new);
+
+        Ok(Self {
+            lifecycle: Arc::new(Lifecycle::default()),
+            config: Arc::new(config),
+            backend,
+            favicon,
+            allowlist,
+        })
+    }
+
+    fn text(&self, template: &str) -> RawJsonText {
+        RawJsonText::from_ampersand(&config::render(template, self.lifecycle.elapsed()))
     }
 
-    async fn handle_request(&self, stream: TcpStream) -> anyhow::Result<()> {
-        let is_proxy = *self.is_proxy.lock().unwrap();
-        if is_proxy {
-            self.handle_proxy(stream).await?;
+    /// ハンドシェイクを読んだ後の接続を処理する
+    async fn handle_request(&self, conn: Connection, handshake: Handshake) -> anyhow::Result<()> {
+        if self.lifecycle.state() == State::Running {
+            self.handle_proxy(conn, handshake).await?;
         } else {
-            let mut std_stream = stream.into_std()?;
-            std_stream.set_nonblocking(false)?;
-            self.handle_motd(&mut std_stream).await?;
+            self.handle_motd(conn, handshake).await?;
         }
 
         Ok(())
     }
 
-    async fn handle_proxy(&self, mut client_conn: TcpStream) -> anyhow::Result<()> {
-        let mut main_server_conn = TcpStream::connect(&self.server_address).await?;
+    async fn handle_proxy(
+        &self,
+        mut client_conn: Connection,
+        mut handshake: Handshake,
+    ) -> anyhow::Result<()> {
+        let (host, port) = self.server_address().await?;
+        let mut server_conn = Connection::new(&host, port).await?;
+
+        // 振り分けのために読んだハンドシェイクを送り直す
+        let forwarding = &self.config.forwarding;
+        if forwarding.rewrite_host {
+            handshake.rewrite_host(&host, port);
+        }
+        if forwarding.transfer_as_login && handshake.next_status == 0x03 {
+            handshake.next_status = 0x02;
+        }
+        let is_login = handshake.next_status != 0x01;
+        let protocol = handshake.version;
+        server_conn.send_packet(handshake).await?;
+
+        if is_login && forwarding.inspect_login {
+            // 中身はバージョンによって異なるので、読めた場合だけ記録してそのまま送る
+            let packet = client_conn.read_raw_packet().await?;
+            if let Ok(login_start) = packet.decode_versioned::<LoginStart>(protocol) {
+                println!(
+                    "[{}] {} が接続しました。",
+                    self.config.name, login_start.name
+                );
+            }
+            server_conn.send_packet(packet).await?;
+        }
+
+        // ここから先は中身を見ずに中継する。読み込み済みで未処理のバイト列を先に送る。
+        let (mut client_conn, buffered) = client_conn.into_parts();
+        let (mut main_server_conn, _) = server_conn.into_parts();
+        main_server_conn.write_all(&buffered).await?;
+
         let (mut client_recv, mut client_send) = client_conn.split();
         let (mut server_recv, mut server_send) = main_server_conn.split();
 
@@ -69,67 +159,59 @@ impl Server {
         Ok(())
     }
 
-    async fn handle_motd(&self, stream: &mut std::net::TcpStream) -> anyhow::Result<()> {
-        let handshake: Handshake = read_packet(stream)?;
-
+    async fn handle_motd(&self, mut conn: Connection, handshake: Handshake) -> anyhow::Result<()> {
         match handshake.next_status {
-            0x01 => {
-                let _status_request: StatusRequest = read_packet(stream)?;
-
-                let status_response = status_response::StatusResponse {
-                    version: Version {
-                        name: "Not Proxying".to_string(),
-                        protocol: 767,
-                    },
-                    players: Players {
-                        max: 100,
-                        online: 1,
-                        sample: None,
-                    },
-                    description: RawJsonText::String("接続してプロキシを開始".to_string()),
-                    modinfo: None,
-                    favicon: None,
-                };
-                stream.write_packet(status_response)?;
-
-                let ping: Ping = read_packet(stream)?;
-                stream.write_packet(ping)?;
-            }
-            0x02 => {
-                if self.start_instance().await.is_ok() {
-                    stream.write_packet(DisconnectLogin {
-                        reason: RawJsonText::String(
-                            "プロキシを開始しました。再度接続してください。".to_string(),
-                        ),
-                    })?;
-
-                    let mut is_server_active = false;
-                    while !is_server_active {
-                        let address = &self.server_address.split(':').collect::<Vec<_>>();
-                        println!("サーバーへの疎通を確認します。");
-                        if let Ok(mut client) =
-                            minecraft::client::Client::new(address[0], address[1].parse()?)
-                        {
-                            is_server_active = client.status().is_ok();
-                        }
+            0x01 => loop {
+                let packet = conn.read_raw_packet().await?;
+                match packet.id {
+                    0x00 => {
+                        let status_response = self.status_response(handshake.version).await;
+                        conn.send_packet(status_response).await?;
+                    }
+                    0x01 => {
+                        let ping: Ping = packet.decode()?;
+                        conn.send_packet(Pong {
+                            payload: ping.payload,
+                        })
+                        .await?;
+                        break;
+                    }
+                    id => return Err(anyhow::anyhow!("Unexpected packet in status: {:#04x}", id)),
+                }
+            },
+            0x02 | 0x03 => {
+                let login_start: LoginStart = conn
+                    .read_raw_packet()
+                    .await?
+                    .decode_versioned(handshake.version)?;
+                if !self.may_wake(&login_start, conn.peer_addr()?) {
+                    let reason = self.text(&self.config.messages.not_allowed);
+                    conn.send_packet(DisconnectLogin { reason }).await?;
+                    return Ok(());
+                }
+                if Limbo::supports(handshake.version) {
+                    return self.hold_in_limbo(conn, &handshake, &login_start).await;
+                }
 
-                        if is_server_active {
-                            println!("接続を確認できました。プロキシを開始します。")
+                let messages = &self.config.messages;
+                let reason = match self.lifecycle.state() {
+                    State::Stopped | State::Failed => {
+                        if self.wake_player(&login_start).is_ok() {
+                            &messages.waking
                         } else {
-                            println!("接続できませんでした。20s後に再接続します。");
-                            thread::sleep(Duration::from_secs(20));
+                            &messages.starting
                         }
                     }
+                    State::Starting => &messages.starting,
+                    State::Stopping => &messages.stopping,
+                    State::Running => &messages.running,
+                };
 
-                    let mut is_proxy = self.is_proxy.lock().unwrap();
-                    *is_proxy = true;
-                } else {
-                    stream.write_packet(DisconnectLogin {
-                        reason: RawJsonText::String(
-                            "サーバを起動できませんでした。後ほど試してください。".to_string(),
-                        ),
-                    })?;
+                let mut reason = self.text(reason);
+                if self.lifecycle.state() == State::Failed {
+                    reason = alert(reason);
                 }
+                conn.send_packet(DisconnectLogin { reason }).await?;
             }
             _ => {
                 return Err(anyhow::anyhow!(
@@ -142,113 +224,1414 @@ impl Server {
         Ok(())
     }
 
-    async fn start_instance(&self) -> anyhow::Result<()> {
-        let region_provider = RegionProviderChain::default_provider().or_else("ap-northeast-1");
-        let config = aws_config::defaults(BehaviorVersion::v2024_03_28())
-            .region(region_provider)
-            .load()
-            .await;
-        let client = Client::new(&config);
+    /// 起動済みならサーバ自身の応答をそのまま返し、それ以外は状態を表示する
+    /// 停止中でも「バージョンが違う」と表示されないよう、クライアントのプロトコルをそのまま返す
+    async fn status_response(&self, protocol: i32) -> StatusResponse {
+        let state = self.lifecycle.state();
+        if state == State::Running {
+            if let Ok(client) = self.connect_client().await {
+                let mut client = client.with_protocol(protocol);
+                if let Ok(status) = client.status().await {
+                    return status;
+                }
+            }
+        }
 
-        let _start_instances_response = client
-            .start_instances()
-            .instance_ids(&self.instance_id)
-            .send()
-            .await?;
+        let (name, description) = self.describe(state);
 
-        Ok(())
+        StatusResponse {
+            version: Version {
+                name: name.to_string(),
+                protocol,
+            },
+            players: Players {
+                max: 0,
+                online: 0,
+                sample: None,
+            },
+            description: self.text(description),
+            modinfo: None,
+            favicon: self.favicon.clone(),
+        }
     }
 
-    async fn stop_instance(&self) -> anyhow::Result<()> {
-        let region_provider = RegionProviderChain::default_provider().or_else("ap-northeast-1");
-        let config = aws_config::defaults(BehaviorVersion::v2024_03_28())
-            .region(region_provider)
-            .load()
-            .await;
-        let client = Client::new(&config);
+    /// 状態の名前と、サーバ一覧に表示する説明文
+    fn describe(&self, state: State) -> (&'static str, &str) {
+        let motd = &self.config.motd;
+        match state {
+            State::Stopped => ("スリープ中", &motd.stopped),
+            State::Starting => ("起動中", &motd.starting),
+            State::Running => ("起動済み", &motd.running),
+            State::Stopping => ("停止中", &motd.stopping),
+            State::Failed => ("起動失敗", &motd.failed),
+        }
+    }
 
-        let _stop_instances_response = client
-            .stop_instances()
-            .instance_ids(&self.instance_id)
-            .send()
+    /// 起動済みでないときに Bedrock の Pong で返す内容
+    ///
+    /// Bedrock の MOTD は 2 行なので、説明文が 1 行なら 2 行目に状態の名前を入れる。
+    fn bedrock_status(&self, guid: i64, listen: SocketAddr) -> BedrockStatus {
+        let (name, description) = self.describe(self.lifecycle.state());
+        let motd = self.text(description).to_legacy();
+        let (motd, sub_motd) = motd.split_once('\n').unwrap_or((&motd, name));
+
+        BedrockStatus {
+            edition: "MCPE".to_string(),
+            motd: motd.to_string(),
+            protocol: bedrock::PROTOCOL,
+            version: bedrock::VERSION.to_string(),
+            online_players: 0,
+            max_players: 0,
+            server_guid: guid,
+            sub_motd: sub_motd.to_string(),
+            game_mode: "Survival".to_string(),
+            port_v4: listen.is_ipv4().then_some(listen.port()),
+            port_v6: listen.is_ipv6().then_some(listen.port()),
+        }
+    }
+
+    /// 起動済みでないときに Query で返す内容。map に状態を入れる。
+    fn query_stat(&self, listen: SocketAddr) -> FullStat {
+        let motd = &self.config.motd;
+        let (map, description) = match self.lifecycle.state() {
+            State::Stopped => ("sleeping", &motd.stopped),
+            State::Starting => ("starting", &motd.starting),
+            State::Running => ("running", &motd.running),
+            State::Stopping => ("stopping", &motd.stopping),
+            State::Failed => ("failed", &motd.failed),
+        };
+
+        FullStat {
+            motd: self.text(description).to_plain(),
+            game_type: "SMP".to_string(),
+            game_id: "MINECRAFT".to_string(),
+            version: version::name(version::LATEST).unwrap_or_default(),
+            plugins: String::new(),
+            map: map.to_string(),
+            num_players: 0,
+            max_players: 0,
+            host_port: listen.port(),
+            host_ip: listen.ip().to_string(),
+            players: vec![],
+        }
+    }
+
+    /// 起動が終わるまで Limbo で待たせ、終わったら Transfer でこのプロキシに接続し直させる
+    ///
+    /// 接続し直したときのハンドシェイクは next_state が 3 (Transfer) になる。
+    /// `forwarding.transfer_as_login` を無効にした場合は、サーバ側の
+    /// server.properties で `accepts-transfers=true` にしておく必要がある。
+    async fn hold_in_limbo(
+        &self,
+        conn: Connection,
+        handshake: &Handshake,
+        login_start: &LoginStart,
+    ) -> anyhow::Result<()> {
+        if matches!(self.lifecycle.state(), State::Stopped | State::Failed) {
+            self.wake_player(login_start).ok();
+        }
+
+        let messages = &self.config.messages;
+        let mut limbo = Limbo::join(conn, handshake.version, login_start).await?;
+        limbo
+            .title(
+                self.text(&messages.limbo_title),
+                self.text(&messages.limbo_subtitle),
+            )
             .await?;
 
+        loop {
+            if !limbo.idle(LIMBO_TICK_INTERVAL).await {
+                return Ok(());
+            }
+
+            let message = match self.lifecycle.state() {
+                State::Running => {
+                    return limbo.transfer(handshake.address(), handshake.port).await;
+                }
+                State::Failed => {
+                    let reason = alert(self.text(&messages.failed));
+                    return limbo.disconnect(reason).await;
+                }
+                State::Starting => &messages.limbo_starting,
+                State::Stopping => &messages.limbo_stopping,
+                State::Stopped => {
+                    self.wake_player(login_start).ok();
+                    &messages.limbo_waiting
+                }
+            };
+            limbo.tick(self.text(message)).await?;
+        }
+    }
+
+    /// allowlist が無ければ誰でも起動できる。あれば判定を監査ログに残す。
+    fn may_wake(&self, player: &LoginStart, peer: SocketAddr) -> bool {
+        let Some(allowlist) = &self.allowlist else {
+            return true;
+        };
+
+        let allowed = allowlist.allows(&player.name, player.uuid);
+        println!(
+            "{}",
+            AuditEntry {
+                at: SystemTime::now(),
+                server: &self.config.name,
+                allowed,
+                name: &player.name,
+                uuid: player.uuid,
+                peer,
+            }
+        );
+        allowed
+    }
+
+    fn wake_player(&self, player: &LoginStart) -> anyhow::Result<()> {
+        self.wake(&format!("{} がログイン", player.name))
+    }
+
+    /// Bedrock は接続の時点ではプレイヤーが分からないので、allowlist があれば起動しない
+    fn wake_bedrock(&self, from: SocketAddr) {
+        if self.allowlist.is_some() {
+            println!(
+                "[{}] Bedrock クライアント ({}) は allowlist を確認できないため、起動しませんでした。",
+                self.config.name, from
+            );
+            return;
+        }
+
+        self.wake(&format!("Bedrock クライアント ({}) が接続", from))
+            .ok();
+    }
+
+    /// `Starting` に遷移できたときだけ起動処理を始める
+    fn wake(&self, reason: &str) -> anyhow::Result<()> {
+        self.lifecycle.transition(State::Starting, reason)?;
+        println!("[{}] サーバを起動します。({})", self.config.name, reason);
+
+        tokio::spawn({
+            let server = self.clone();
+            async move {
+                let result = match server.backend.start().await {
+                    Ok(()) => server.wait_until_reachable().await,
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(()) => {
+                        server
+                            .lifecycle
+                            .transition(State::Running, "疎通を確認")
+                            .ok();
+                        println!(
+                            "[{}] 接続を確認できました。プロキシを開始します。",
+                            server.config.name
+                        );
+                    }
+                    Err(e) => {
+                        server
+                            .lifecycle
+                            .transition(State::Failed, &e.to_string())
+                            .ok();
+                        eprintln!("[{}] サーバを起動できませんでした: {e}", server.config.name);
+                    }
+                }
+            }
+        });
+
         Ok(())
     }
+
+    async fn wait_until_reachable(&self) -> anyhow::Result<()> {
+        let started = time::Instant::now();
+        let boot_timeout = self.config.boot_timeout();
+
+        loop {
+            println!("[{}] サーバーへの疎通を確認します。", self.config.name);
+            if let Ok(mut client) = self.connect_client().await {
+                if client.status().await.is_ok() {
+                    return Ok(());
+                }
+            }
+
+            if boot_timeout <= started.elapsed() {
+                return Err(anyhow::anyhow!(
+                    "Server did not respond within {:?}",
+                    boot_timeout
+                ));
+            }
+
+            println!(
+                "[{}] 接続できませんでした。20s後に再接続します。",
+                self.config.name
+            );
+            time::sleep(BOOT_CHECK_INTERVAL).await;
+        }
+    }
+
+    /// RCON が設定されていれば、ゲーム内で警告してセーブしてから止める
+    ///
+    /// 警告中に誰かが参加したら中止して `false` を返す。
+    /// RCON に接続できなければ警告せずに止める。
+    async fn shutdown(&self, reason: &str) -> anyhow::Result<bool> {
+        let mut rcon = match &self.config.rcon {
+            Some(config) => match self.connect_rcon(config).await {
+                Ok(rcon) => Some(rcon),
+                Err(e) => {
+                    eprintln!(
+                        "[{}] RCON に接続できませんでした。警告せずに停止します: {e}",
+                        self.config.name
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Some(rcon) = &mut rcon {
+            if !self.count_down(rcon).await? {
+                return Ok(false);
+            }
+            rcon.command("save-all flush").await?;
+        }
+
+        self.lifecycle.transition(State::Stopping, reason)?;
+
+        if let Some(mut rcon) = rcon {
+            // 応答を返す前に切断されることがある
+            rcon.command("stop").await.ok();
+            self.wait_until_unreachable().await;
+        }
+
+        let result = match self.backend.stop().await {
+            Ok(()) => self.wait_until_stopped().await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                self.lifecycle.transition(State::Stopped, reason)?;
+                Ok(true)
+            }
+            Err(e) => {
+                self.lifecycle.transition(State::Failed, &e.to_string())?;
+                Err(e)
+            }
+        }
+    }
+
+    /// `stop` は停止を始めるだけのことがある (EC2 の stopping など) ので、
+    /// ホストが止まったと言うまで `Stopping` のまま待つ
+    async fn wait_until_stopped(&self) -> anyhow::Result<()> {
+        let started = time::Instant::now();
+        loop {
+            if self.backend.state().await? == BackendState::Stopped {
+                return Ok(());
+            }
+
+            if BACKEND_STOP_TIMEOUT <= started.elapsed() {
+                return Err(anyhow::anyhow!(
+                    "Backend did not stop within {:?}",
+                    BACKEND_STOP_TIMEOUT
+                ));
+            }
+            time::sleep(BACKEND_STOP_CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn connect_rcon(&self, config: &RconConfig) -> anyhow::Result<Rcon> {
+        let (host, _) = self.server_address().await?;
+        Ok(Rcon::connect(&host, config.port, &config.password).await?)
+    }
+
+    /// `countdown` の秒数ごとに say と title で知らせる。誰かが参加したら `false`。
+    async fn count_down(&self, rcon: &mut Rcon) -> anyhow::Result<bool> {
+        let Some(config) = &self.config.rcon else {
+            return Ok(true);
+        };
+        let messages = &self.config.messages;
+
+        let mut steps = config.countdown.iter().peekable();
+        while let Some(&remaining) = steps.next() {
+            let warning = messages
+                .shutdown_warning
+                .replace("{remaining}", &remaining.to_string());
+            broadcast(rcon, &self.text(&warning)).await?;
+
+            let next = steps.peek().map_or(0, |&&next| next);
+            time::sleep(Duration::from_secs(remaining - next)).await;
+
+            if self.online_players().await > 0 {
+                broadcast(rcon, &self.text(&messages.shutdown_cancelled)).await?;
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// stop の後、ポートが閉じるまで待つ
+    async fn wait_until_unreachable(&self) {
+        let started = time::Instant::now();
+        while started.elapsed() < SERVER_STOP_TIMEOUT {
+            let Ok(mut client) = self.connect_client().await else {
+                return;
+            };
+            if client.status().await.is_err() {
+                return;
+            }
+            time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// 繋がらなければ 0 人とみなす
+    async fn online_players(&self) -> usize {
+        if let Some(port) = self.config.query_port {
+            return self.query_online_players(port).await.unwrap_or(0);
+        }
+
+        match self.connect_client().await {
+            Ok(mut client) => client.get_online_players_count().await.unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+
+    async fn query_online_players(&self, port: u16) -> anyhow::Result<usize> {
+        let (host, _) = self.server_address().await?;
+        let mut query = Query::connect(&host, port).await?;
+        Ok(query.basic_stat().await?.num_players)
+    }
+
+    /// `query_port` が無ければサーバの既定どおりゲームと同じポート
+    async fn query_address(&self) -> anyhow::Result<(String, u16)> {
+        let (host, port) = self.server_address().await?;
+        Ok((host, self.config.query_port.unwrap_or(port)))
+    }
+
+    async fn bedrock_address(&self, config: &BedrockConfig) -> anyhow::Result<(String, u16)> {
+        let (host, _) = self.server_address().await?;
+        Ok((host, config.port))
+    }
+
+    async fn server_address(&self) -> anyhow::Result<(String, u16)> {
+        let address = self.backend.address().await?;
+        match split_address(&address) {
+            Some((host, Some(port))) => Ok((host.to_string(), port)),
+            _ => Err(anyhow::anyhow!("Invalid server address: {}", address)),
+        }
+    }
+
+    async fn connect_client(&self) -> anyhow::Result<client::Client> {
+        let (host, port) = self.server_address().await?;
+        Ok(client::Client::new(&host, port).await?)
+    }
 }
 
 impl Clone for Server {
     fn clone(&self) -> Self {
         Server {
-            is_proxy: Arc::clone(&self.is_proxy),
-            server_address: self.server_address.to_string(),
-            client_address: self.client_address.to_string(),
-            instance_id: self.instance_id.to_string(),
+            lifecycle: Arc::clone(&self.lifecycle),
+            config: Arc::clone(&self.config),
+            backend: Arc::clone(&self.backend),
+            favicon: self.favicon.clone(),
+            allowlist: self.allowlist.clone(),
         }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenvy::dotenv().ok();
+/// 同じアドレスで待ち受けるサーバをハンドシェイクのホスト名で振り分ける
+struct Router {
+    listen: String,
+    servers: Vec<Server>,
+}
 
-    let client_addr = env::var("CLIENT_ADDRESS").unwrap();
-    let server_addr = env::var("SERVER_ADDRESS").unwrap();
-    let instance_id = env::var("INSTANCE_ID").unwrap();
+impl Router {
+    fn route(&self, hostname: &str) -> Option<&Server> {
+        self.servers
+            .iter()
+            .find(|server| {
+                server
+                    .config
+                    .hostnames
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(hostname))
+            })
+            .or_else(|| self.servers.iter().find(|server| server.config.default))
+            .or_else(|| {
+                self.servers
+                    .iter()
+                    .find(|server| server.config.hostnames.is_empty())
+            })
+    }
+
+    async fn handle_connection(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        if legacy::is_legacy_ping(&stream).await? {
+            let ping = legacy::read_ping(&mut stream).await?;
+            let hostname = ping.hostname().unwrap_or_default().to_ascii_lowercase();
+            let Some(server) = self.route(&hostname) else {
+                return Err(anyhow::anyhow!("No server for host: {}", hostname));
+            };
 
-    let server = Server::new(&client_addr, &server_addr, &instance_id);
-    let listener = TcpListener::bind(&server.client_address).await?;
+            let protocol = ping.protocol().unwrap_or(legacy::LEGACY_PROTOCOL);
+            let status = server.status_response(protocol).await;
+            legacy::write_status(&mut stream, &ping, &status).await?;
+            return Ok(());
+        }
 
-    let mut interval = time::interval(USAGE_CHECK_INTERVAL);
-    let split = server_addr.split(':').map(String::from).collect::<Vec<_>>();
+        let mut conn = Connection::from_stream(stream);
+        let handshake: Handshake = conn.read_packet().await?;
+        let hostname = handshake.hostname();
 
-    tokio::task::spawn({
-        let server = server.clone();
-        let is_proxy = server.clone().is_proxy.clone();
-        async move {
-            let mut inactive_count = 0;
+        let Some(server) = self.route(&hostname) else {
+            if handshake.next_status != 0x01 {
+                conn.send_packet(DisconnectLogin {
+                    reason: Object::text("このアドレスに対応するサーバはありません。")
+                        .color(Color::Red)
+                        .into(),
+                })
+                .await?;
+            }
 
-            loop {
-                interval.tick().await;
+            return Err(anyhow::anyhow!("No server for host: {}", hostname));
+        };
 
-                {
-                    let is_proxy = is_proxy.lock().unwrap();
-                    if !*is_proxy {
-                        continue;
-                    }
-                }
+        server.handle_request(conn, handshake).await
+    }
+}
+
+/// 送信元ごとにサーバへ UDP を中継する
+///
+/// サーバは Query のトークンや RakNet のセッションを送信元のアドレスで管理するので、
+/// 同じ相手からのパケットは同じソケットで送る。
+struct UdpRelay {
+    /// 待ち受けているソケット。応答はここから送信元に返す。
+    socket: Arc<UdpSocket>,
+    /// 接続し終えるまでは空
+    relays: Mutex<HashMap<SocketAddr, Arc<OnceCell<Arc<UdpSocket>>>>>,
+    max_relays: usize,
+}
+
+impl UdpRelay {
+    fn new(socket: Arc<UdpSocket>, max_relays: usize) -> Arc<Self> {
+        Arc::new(Self {
+            socket,
+            relays: Mutex::new(HashMap::new()),
+            max_relays,
+        })
+    }
 
-                let mut client = client::Client::new(&split[0], split[1].parse().unwrap()).unwrap();
-                let status = client.status().unwrap();
-                if status.players.online == 0 {
-                    inactive_count += 1;
-                } else {
-                    inactive_count = 0;
+    /// 送信元に対応する中継が無ければ `target` に繋ぐ
+    ///
+    /// 同じ送信元から続けて届いたパケットは、接続を待って同じ中継で送る。
+    /// 中継が `max_relays` 個あるときは、期限が切れるまで新しい送信元を断る。
+    async fn send(
+        self: &Arc<Self>,
+        packet: &[u8],
+        from: SocketAddr,
+        target: impl Future<Output = anyhow::Result<(String, u16)>>,
+    ) -> anyhow::Result<()> {
+        let slot = {
+            let mut relays = self.relays.lock().unwrap();
+            if !relays.contains_key(&from) && self.max_relays <= relays.len() {
+                return Err(anyhow::anyhow!(
+                    "Too many UDP relays, dropping packet from {}",
+                    from
+                ));
+            }
+            Arc::clone(relays.entry(from).or_default())
+        };
+
+        let mut connected = false;
+        let relay = slot
+            .get_or_try_init(|| async {
+                let (host, port) = target.await?;
+                let relay = Arc::new(query::connect_socket(&host, port).await?);
+                connected = true;
+                anyhow::Ok(relay)
+            })
+            .await;
+        let relay = match relay {
+            Ok(relay) => Arc::clone(relay),
+            Err(e) => {
+                self.remove(from, &slot);
+                return Err(e);
+            }
+        };
+        if connected {
+            tokio::spawn(Arc::clone(self).relay_back(slot, Arc::clone(&relay), from));
+        }
+        relay.send(packet).await?;
+
+        Ok(())
+    }
+
+    /// サーバからの応答を送信元に返す。しばらく何も届かなければ中継をやめる。
+    async fn relay_back(
+        self: Arc<Self>,
+        slot: Arc<OnceCell<Arc<UdpSocket>>>,
+        relay: Arc<UdpSocket>,
+        to: SocketAddr,
+    ) {
+        let mut buf = vec![0_u8; 65535];
+        while let Ok(Ok(len)) = time::timeout(UDP_RELAY_TIMEOUT, relay.recv(&mut buf)).await {
+            if self.socket.send_to(&buf[..len], to).await.is_err() {
+                break;
+            }
+        }
+        self.remove(to, &slot);
+    }
+
+    /// 同じ送信元に作り直された新しい中継は残す
+    fn remove(&self, from: SocketAddr, slot: &Arc<OnceCell<Arc<UdpSocket>>>) {
+        let mut relays = self.relays.lock().unwrap();
+        if relays
+            .get(&from)
+            .is_some_and(|current| Arc::ptr_eq(current, slot))
+        {
+            relays.remove(&from);
+        }
+    }
+}
+
+/// listen と同じ UDP ポートに来た Query を処理する
+///
+/// UDP にはホスト名が無いので、default (またはホスト名の無い) サーバが応答する。
+/// そのサーバで `answer_query` を設定したときだけ待ち受ける。
+struct QueryResponder {
+    socket: Arc<UdpSocket>,
+    router: Arc<Router>,
+    tokens: ChallengeTokens,
+    relay: Arc<UdpRelay>,
+}
+
+impl QueryResponder {
+    /// 起動済みならサーバに中継し、それ以外は状態を返す
+    async fn handle_packet(&self, packet: &[u8], from: SocketAddr) -> anyhow::Result<()> {
+        let Some(server) = self.router.route("") else {
+            return Err(anyhow::anyhow!("No server for query"));
+        };
+
+        if server.lifecycle.state() == State::Running {
+            return self.relay.send(packet, from, server.query_address()).await;
+        }
+
+        let Some(request) = Request::parse(packet) else {
+            return Err(anyhow::anyhow!("Invalid query packet from {}", from));
+        };
+        let listen: SocketAddr = self.router.listen.parse()?;
+        let response = match request {
+            Request::Handshake { session_id } => {
+                query::encode_handshake(session_id, self.tokens.issue(from))
+            }
+            // サーバと同じく、トークンが違えば何も返さない
+            Request::BasicStat { token, .. } | Request::FullStat { token, .. }
+                if !self.tokens.verify(from, token) =>
+            {
+                return Ok(());
+            }
+            Request::BasicStat { session_id, .. } => {
+                server.query_stat(listen).basic().encode(session_id)
+            }
+            Request::FullStat { session_id, .. } => server.query_stat(listen).encode(session_id),
+        };
+        self.socket.send_to(&response, from).await?;
+
+        Ok(())
+    }
+}
+
+async fn listen_query(router: Arc<Router>) -> anyhow::Result<()> {
+    let socket = Arc::new(UdpSocket::bind(&router.listen).await?);
+    let responder = Arc::new(QueryResponder {
+        socket: Arc::clone(&socket),
+        router,
+        tokens: ChallengeTokens::default(),
+        relay: UdpRelay::new(socket, MAX_UDP_RELAYS),
+    });
+
+    let mut buf = vec![0_u8; 65535];
+    loop {
+        let (len, from) = responder.socket.recv_from(&mut buf).await?;
+        let packet = buf[..len].to_vec();
+        tokio::spawn({
+            let responder = Arc::clone(&responder);
+            async move {
+                if let Err(e) = responder.handle_packet(&packet, from).await {
+                    eprintln!("[{}] Error handling query: {e}", responder.router.listen);
                 }
+            }
+        });
+    }
+}
 
-                if inactive_count >= 3 {
-                    server.stop_instance().await.unwrap();
+/// Bedrock Edition の RakNet を処理する
+///
+/// 停止中は Pong で状態を返し、接続しようとしたら起動する。起動済みならサーバに中継する。
+struct BedrockResponder {
+    socket: Arc<UdpSocket>,
+    server: Server,
+    listen: SocketAddr,
+    /// 停止中の Pong に載せるサーバの ID
+    guid: i64,
+    relay: Arc<UdpRelay>,
+}
 
-                    {
-                        let mut is_proxy = is_proxy.lock().unwrap();
-                        *is_proxy = false;
-                    }
+impl BedrockResponder {
+    async fn handle_packet(&self, packet: &[u8], from: SocketAddr) -> anyhow::Result<()> {
+        let server = &self.server;
+        let Some(config) = &server.config.bedrock else {
+            return Ok(());
+        };
+
+        if server.lifecycle.state() == State::Running {
+            return self
+                .relay
+                .send(packet, from, server.bedrock_address(config))
+                .await;
+        }
 
-                    println!("アクセスがなかったためサーバとプロキシを停止しました。");
+        // 停止前のセッションの続きなどは無視する
+        match bedrock::Request::parse(packet) {
+            Some(bedrock::Request::Ping { time }) => {
+                let status = server.bedrock_status(self.guid, self.listen);
+                self.socket
+                    .send_to(&bedrock::encode_pong(time, &status), from)
+                    .await?;
+            }
+            // 接続は完了させられないので、起動だけしてクライアントには再接続してもらう
+            Some(bedrock::Request::OpenConnection) => {
+                if matches!(server.lifecycle.state(), State::Stopped | State::Failed) {
+                    server.wake_bedrock(from);
                 }
             }
+            None => {}
         }
+
+        Ok(())
+    }
+}
+
+async fn listen_bedrock(server: Server, config: &BedrockConfig) -> anyhow::Result<()> {
+    let socket = Arc::new(UdpSocket::bind(&config.listen).await?);
+    let responder = Arc::new(BedrockResponder {
+        socket: Arc::clone(&socket),
+        listen: socket.local_addr()?,
+        server,
+        guid: bedrock::random_guid(),
+        relay: UdpRelay::new(socket, MAX_BEDROCK_RELAYS),
     });
 
+    let mut buf = vec![0_u8; 65535];
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (len, from) = responder.socket.recv_from(&mut buf).await?;
+        let packet = buf[..len].to_vec();
         tokio::spawn({
-            let server = server.clone();
+            let responder = Arc::clone(&responder);
+            async move {
+                if let Err(e) = responder.handle_packet(&packet, from).await {
+                    eprintln!(
+                        "[{}] Error handling bedrock packet: {e}",
+                        responder.server.config.name
+                    );
+                }
+            }
+        });
+    }
+}
+
+async fn watch_idle(server: Server) {
+    let mut interval = time::interval(server.config.check_interval());
+    let mut inactive_count = 0;
+
+    loop {
+        interval.tick().await;
+
+        if server.lifecycle.state() != State::Running {
+            inactive_count = 0;
+            continue;
+        }
+
+        if server.online_players().await == 0 {
+            inactive_count += 1;
+        } else {
+            inactive_count = 0;
+        }
+
+        if inactive_count >= server.config.idle_checks() {
+            inactive_count = 0;
+            match server.shutdown("アクセスなし").await {
+                Ok(true) => println!(
+                    "[{}] アクセスがなかったためサーバとプロキシを停止しました。",
+                    server.config.name
+                ),
+                Ok(false) => println!(
+                    "[{}] プレイヤーが参加したため停止を中止しました。",
+                    server.config.name
+                ),
+                Err(e) => eprintln!("[{}] サーバを停止できませんでした: {e}", server.config.name),
+            }
+        }
+    }
+}
+
+async fn listen(router: Router) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&router.listen).await?;
+    let router = Arc::new(router);
+
+    // Query が無くてもプレイヤーは接続できるので、待ち受けられなくても続ける
+    if router
+        .route("")
+        .is_some_and(|server| server.config.answer_query)
+    {
+        tokio::spawn({
+            let router = Arc::clone(&router);
+            async move {
+                if let Err(e) = listen_query(Arc::clone(&router)).await {
+                    eprintln!("[{}] Query を待ち受けられませんでした: {e}", router.listen);
+                }
+            }
+        });
+    }
+
+    loop {
+        // 1 つの接続で失敗しても、他のプレイヤーは受け付け続ける
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("[{}] 接続を受け付けられませんでした: {e}", router.listen);
+                time::sleep(ACCEPT_RETRY_INTERVAL).await;
+                continue;
+            }
+        };
+        tokio::spawn({
+            let router = Arc::clone(&router);
             async move {
-                if let Err(e) = server.handle_request(stream).await {
-                    eprintln!("Error handling request: {e}");
+                if let Err(e) = router.handle_connection(stream).await {
+                    eprintln!("[{}] Error handling request: {e}", router.listen);
                 }
             }
         });
     }
 }
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+
+    let path = env::args()
+        .nth(1)
+        .or_else(|| env::var("PROXY_CONFIG").ok())
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+    let config = match Config::load(&PathBuf::from(&path)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("設定ファイルを読み込めませんでした: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut routers: Vec<Router> = vec![];
+    for server_config in config.servers {
+        let server = Server::new(server_config)?;
+
+        if server.backend.state().await? == BackendState::Running {
+            server.lifecycle.transition(State::Starting, "起動済み")?;
+            server.lifecycle.transition(State::Running, "起動済み")?;
+        }
+        tokio::spawn(watch_idle(server.clone()));
+        if server.config.bedrock.is_some() {
+            tokio::spawn({
+                let server = server.clone();
+                async move {
+                    let Some(config) = &server.config.bedrock else {
+                        return;
+                    };
+                    if let Err(e) = listen_bedrock(server.clone(), config).await {
+                        eprintln!(
+                            "[{}] Bedrock を待ち受けられませんでした: {e}",
+                            server.config.name
+                        );
+                    }
+                }
+            });
+        }
+
+        match routers
+            .iter_mut()
+            .find(|r| r.listen == server.config.listen)
+        {
+            Some(router) => router.servers.push(server),
+            None => routers.push(Router {
+                listen: server.config.listen.clone(),
+                servers: vec![server],
+            }),
+        }
+    }
+
+    let mut handles = vec![];
+    for router in routers {
+        handles.push(tokio::spawn(listen(router)));
+    }
+
+    for handle in handles {
+        handle.await??;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agent::backend::MockBackend;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use agent::minecraft::{
+        codec::PacketCodec,
+        packet::{PacketEncoder, RawPacket, Versioned},
+        rcon::fake::FakeRcon,
+    };
+    use bytes::BytesMut;
+    use tokio::io::AsyncReadExt;
+    use tokio_util::codec::Encoder;
+
+    /// `extra` は `[servers.backend]` より前に入れる設定
+    fn mock_server(extra: &str) -> (Server, Arc<MockBackend>) {
+        mock_server_at("127.0.0.1:1", extra)
+    }
+
+    fn mock_server_at(address: &str, extra: &str) -> (Server, Arc<MockBackend>) {
+        let config = Config::parse(&format!(
+            r#"
+            [[servers]]
+            name = "test"
+            listen = "127.0.0.1:25565"
+            address = "{address}"
+            {extra}
+
+            [servers.backend]
+            kind = "mock"
+            "#
+        ))
+        .unwrap();
+        let config = config.servers.into_iter().next().unwrap();
+        let backend = Arc::new(MockBackend::new(&config.address));
+
+        let server = Server {
+            lifecycle: Arc::new(Lifecycle::default()),
+            backend: Arc::clone(&backend) as Arc<dyn Backend>,
+            favicon: None,
+            allowlist: config.allowlist().map(Arc::new),
+            config: Arc::new(config),
+        };
+        (server, backend)
+    }
+
+    fn start_running(server: &Server, backend: &MockBackend) {
+        backend.set_state(BackendState::Running);
+        server
+            .lifecycle
+            .transition(State::Starting, "test")
+            .unwrap();
+        server.lifecycle.transition(State::Running, "test").unwrap();
+    }
+
+    /// `online` 人が参加しているように状態応答を返すサーバ。アドレスを返す。
+    async fn status_server(online: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let online = Arc::clone(&online);
+                tokio::spawn(async move {
+                    let mut conn = Connection::from_stream(stream);
+                    let handshake: Handshake = conn.read_packet().await?;
+                    loop {
+                        let packet = conn.read_raw_packet().await?;
+                        if packet.id == 0x01 {
+                            let ping: Ping = packet.decode()?;
+                            conn.send_packet(Pong {
+                                payload: ping.payload,
+                            })
+                            .await?;
+                            return anyhow::Ok(());
+                        }
+                        conn.send_packet(StatusResponse {
+                            version: Version {
+                                name: "test".to_string(),
+                                protocol: handshake.version,
+                            },
+                            players: Players {
+                                max: 20,
+                                online: online.load(Ordering::SeqCst),
+                                sample: None,
+                            },
+                            description: RawJsonText::String(String::new()),
+                            modinfo: None,
+                            favicon: None,
+                        })
+                        .await?;
+                    }
+                });
+            }
+        });
+
+        address
+    }
+
+    /// 別のタスクで進む起動・停止を待つ
+    async fn wait_for(mut done: impl FnMut() -> bool) {
+        time::timeout(Duration::from_secs(5), async {
+            while !done() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn concurrent_logins_start_backend_once() {
+        let (server, backend) = mock_server("");
+
+        let wakes: Vec<_> = (0..8)
+            .map(|i| {
+                let server = server.clone();
+                tokio::spawn(async move { server.wake(&format!("player{i}")).is_ok() })
+            })
+            .collect();
+        let mut woken = 0;
+        for wake in wakes {
+            woken += usize::from(wake.await.unwrap());
+        }
+
+        assert_eq!(woken, 1);
+        wait_for(|| backend.start_count() > 0).await;
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(backend.start_count(), 1);
+        assert_eq!(server.lifecycle.state(), State::Starting);
+    }
+
+    #[tokio::test]
+    async fn failed_start_moves_to_failed() {
+        let (server, backend) = mock_server("");
+        backend.set_fail(true);
+
+        server.wake("test").unwrap();
+        wait_for(|| server.lifecycle.state() == State::Failed).await;
+        assert_eq!(backend.start_count(), 1);
+
+        // 失敗した後は次のログインで再試行できる
+        backend.set_fail(false);
+        server.wake("retry").unwrap();
+        wait_for(|| backend.start_count() == 2).await;
+    }
+
+    #[tokio::test]
+    async fn idle_server_is_stopped() {
+        let (server, backend) = mock_server("check_interval = 1\nidle_timeout = 1");
+        start_running(&server, &backend);
+
+        let watch = tokio::spawn(watch_idle(server.clone()));
+        wait_for(|| server.lifecycle.state() == State::Stopped).await;
+        watch.abort();
+
+        assert_eq!(backend.stop_count(), 1);
+        assert_eq!(backend.start_count(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_stays_stopping_until_backend_stops() {
+        let (server, backend) = mock_server("");
+        start_running(&server, &backend);
+        backend.set_slow_stop(true);
+
+        let shutdown = tokio::spawn({
+            let server = server.clone();
+            async move { server.shutdown("test").await }
+        });
+
+        time::sleep(BACKEND_STOP_CHECK_INTERVAL * 3).await;
+        assert_eq!(server.lifecycle.state(), State::Stopping);
+
+        backend.set_state(BackendState::Stopped);
+        assert!(shutdown.await.unwrap().unwrap());
+        assert_eq!(server.lifecycle.state(), State::Stopped);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_fails_when_backend_never_stops() {
+        let (server, backend) = mock_server("");
+        start_running(&server, &backend);
+        backend.set_slow_stop(true);
+
+        assert!(server.shutdown("test").await.is_err());
+        assert_eq!(server.lifecycle.state(), State::Failed);
+    }
+
+    #[tokio::test]
+    async fn player_joining_mid_countdown_aborts_shutdown() {
+        let rcon = FakeRcon::start("password").await;
+        let online = Arc::new(AtomicUsize::new(0));
+        let address = status_server(Arc::clone(&online)).await;
+        let (server, backend) = mock_server_at(
+            &address,
+            &format!(
+                r#"
+                [servers.rcon]
+                port = {}
+                password = "password"
+                countdown = [2, 1]
+                "#,
+                rcon.port()
+            ),
+        );
+        start_running(&server, &backend);
+
+        let shutdown = tokio::spawn({
+            let server = server.clone();
+            async move { server.shutdown("test").await }
+        });
+
+        // 最初の警告の後に参加する
+        wait_for(|| !rcon.commands().is_empty()).await;
+        online.store(1, Ordering::SeqCst);
+
+        assert!(!shutdown.await.unwrap().unwrap());
+        assert_eq!(server.lifecycle.state(), State::Running);
+        assert_eq!(backend.stop_count(), 0);
+
+        let commands = rcon.commands();
+        assert!(commands[0].starts_with("say "));
+        assert!(commands
+            .iter()
+            .any(|command| command.starts_with("say ") && command.contains("取りやめました")));
+        assert!(!commands
+            .iter()
+            .any(|command| command.starts_with("save-all") || command == "stop"));
+    }
+
+    /// `servers` は `name = ...` から始まる `[[servers]]` の中身
+    fn router(servers: &[&str]) -> Router {
+        let config = servers
+            .iter()
+            .map(|server| {
+                format!(
+                    r#"
+                    [[servers]]
+                    {server}
+                    listen = "127.0.0.1:25565"
+                    address = "127.0.0.1:1"
+
+                    [servers.backend]
+                    kind = "mock"
+                    "#
+                )
+            })
+            .collect::<String>();
+        let config = Config::parse(&config).unwrap();
+
+        Router {
+            listen: "127.0.0.1:25565".to_string(),
+            servers: config
+                .servers
+                .into_iter()
+                .map(|config| Server::new(config).unwrap())
+                .collect(),
+        }
+    }
+
+    fn routed<'a>(router: &'a Router, host: &str) -> Option<&'a str> {
+        let handshake = Handshake {
+            version: version::V1_21,
+            host: host.to_string(),
+            port: 25565,
+            next_status: 2,
+        };
+        router
+            .route(&handshake.hostname())
+            .map(|server| server.config.name.as_str())
+    }
+
+    #[test]
+    fn routes_by_hostname() {
+        let router = router(&[
+            "name = \"survival\"\nhostnames = [\"survival.example.com\"]",
+            "name = \"creative\"\nhostnames = [\"Creative.example.com\"]",
+        ]);
+
+        assert_eq!(routed(&router, "SURVIVAL.example.com"), Some("survival"));
+        assert_eq!(routed(&router, "creative.example.com"), Some("creative"));
+        assert_eq!(
+            routed(&router, "Survival.Example.com.\0FML3\0"),
+            Some("survival")
+        );
+        assert_eq!(routed(&router, "unknown.example.com"), None);
+        assert_eq!(routed(&router, ""), None);
+    }
+
+    #[test]
+    fn unknown_hostname_falls_back_to_default() {
+        let router = router(&[
+            "name = \"survival\"\nhostnames = [\"survival.example.com\"]",
+            "name = \"lobby\"\nhostnames = [\"lobby.example.com\"]\ndefault = true",
+        ]);
+
+        assert_eq!(routed(&router, "survival.example.com"), Some("survival"));
+        assert_eq!(routed(&router, "unknown.example.com"), Some("lobby"));
+        assert_eq!(routed(&router, "192.0.2.1"), Some("lobby"));
+    }
+
+    #[test]
+    fn server_without_hostnames_takes_every_host() {
+        let router = router(&["name = \"survival\""]);
+
+        assert_eq!(routed(&router, "survival.example.com"), Some("survival"));
+        assert_eq!(routed(&router, "192.0.2.1"), Some("survival"));
+        assert_eq!(routed(&router, ""), Some("survival"));
+    }
+
+    #[tokio::test]
+    async fn legacy_ping_without_protocol_gets_legacy_protocol() {
+        let router = router(&["name = \"survival\""]);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        // 1.4 - 1.5 の ping
+        client.write_all(&[0xFE, 0x01]).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        router.handle_connection(stream).await.unwrap();
+
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        let units: Vec<u16> = response[3..]
+            .chunks(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        let response = String::from_utf16(&units).unwrap();
+        let fields: Vec<_> = response.split('\0').collect();
+        assert_eq!(fields[0], "§1");
+        assert_eq!(fields[1], legacy::LEGACY_PROTOCOL.to_string());
+    }
+
+    fn frame<P: PacketEncoder>(packet: P) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        PacketCodec::default().encode(packet, &mut buf).unwrap();
+        buf.to_vec()
+    }
+
+    fn handshake(next_status: i32) -> Handshake {
+        Handshake {
+            version: version::V1_21,
+            host: "example.com".to_string(),
+            port: 25565,
+            next_status,
+        }
+    }
+
+    /// クライアントとして `bytes` を一度に書き込み、プロキシした先で受け取った最初の
+    /// `len` バイトを返す
+    async fn proxy_once(extra: &str, bytes: Vec<u8>, len: usize) -> Vec<u8> {
+        let backend_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = backend_listener.local_addr().unwrap().to_string();
+        let (server, backend) = mock_server_at(&address, extra);
+        start_running(&server, &backend);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listen = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut conn = Connection::from_stream(stream);
+            let handshake: Handshake = conn.read_packet().await?;
+            server.handle_proxy(conn, handshake).await
+        });
+
+        let mut client = TcpStream::connect(listen).await.unwrap();
+        client.write_all(&bytes).await.unwrap();
+
+        let (mut stream, _) = backend_listener.accept().await.unwrap();
+        let mut received = vec![0; len];
+        time::timeout(Duration::from_secs(5), stream.read_exact(&mut received))
+            .await
+            .expect("timed out")
+            .unwrap();
+        received
+    }
+
+    #[tokio::test]
+    async fn pipelined_packets_follow_login_start() {
+        let login_start = frame(Versioned(
+            LoginStart {
+                name: "Steve".to_string(),
+                uuid: Some(uuid::Uuid::from_u128(1)),
+            },
+            version::V1_21,
+        ));
+        // Login Acknowledged を待たずに送られてきた次のパケット
+        let next = frame(RawPacket {
+            id: 0x03,
+            payload: vec![],
+        });
+
+        let mut bytes = frame(handshake(2));
+        bytes.extend(&login_start);
+        bytes.extend(&next);
+        let received = proxy_once("", bytes.clone(), bytes.len()).await;
+
+        assert_eq!(received, bytes);
+    }
+
+    #[tokio::test]
+    async fn transfer_is_forwarded_as_login() {
+        let login_start = frame(Versioned(
+            LoginStart {
+                name: "Steve".to_string(),
+                uuid: Some(uuid::Uuid::from_u128(1)),
+            },
+            version::V1_21,
+        ));
+        let mut bytes = frame(handshake(3));
+        bytes.extend(&login_start);
+
+        let mut expected = frame(handshake(2));
+        expected.extend(&login_start);
+        let received = proxy_once("", bytes.clone(), expected.len()).await;
+        assert_eq!(received, expected);
+
+        let received = proxy_once(
+            "[servers.forwarding]\ntransfer_as_login = false",
+            bytes.clone(),
+            bytes.len(),
+        )
+        .await;
+        assert_eq!(received, bytes);
+    }
+
+    #[tokio::test]
+    async fn concurrent_datagrams_share_one_relay() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let listen = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let relay = UdpRelay::new(listen, MAX_UDP_RELAYS);
+        let from: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+        let connects = Arc::new(AtomicUsize::new(0));
+
+        let sends: Vec<_> = (0..8)
+            .map(|_| {
+                let relay = Arc::clone(&relay);
+                let connects = Arc::clone(&connects);
+                tokio::spawn(async move {
+                    let target = async {
+                        connects.fetch_add(1, Ordering::SeqCst);
+                        // 他のパケットが届くまで接続を終えない
+                        time::sleep(Duration::from_millis(50)).await;
+                        anyhow::Ok(("127.0.0.1".to_string(), port))
+                    };
+                    relay.send(b"ping", from, target).await.unwrap();
+                })
+            })
+            .collect();
+        for send in sends {
+            send.await.unwrap();
+        }
+
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert_eq!(relay.relays.lock().unwrap().len(), 1);
+
+        let mut buf = [0; 16];
+        let (_, source) = server.recv_from(&mut buf).await.unwrap();
+        for _ in 1..8 {
+            assert_eq!(server.recv_from(&mut buf).await.unwrap().1, source);
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_relay_keeps_newer_one() {
+        let listen = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let relay = UdpRelay::new(listen, MAX_UDP_RELAYS);
+        let from: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+        let expired = Arc::new(OnceCell::new());
+        let newer = Arc::new(OnceCell::new());
+        relay
+            .relays
+            .lock()
+            .unwrap()
+            .insert(from, Arc::clone(&newer));
+
+        relay.remove(from, &expired);
+        assert!(relay.relays.lock().unwrap().contains_key(&from));
+
+        relay.remove(from, &newer);
+        assert!(relay.relays.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_new_sources_above_relay_limit() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let listen = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let relay = UdpRelay::new(listen, 2);
+        let target = || async move { anyhow::Ok(("127.0.0.1".to_string(), port)) };
+        let source = |port| SocketAddr::from(([127, 0, 0, 1], port));
+
+        relay.send(b"a", source(50000), target()).await.unwrap();
+        relay.send(b"b", source(50001), target()).await.unwrap();
+        assert!(relay.send(b"c", source(50002), target()).await.is_err());
+        // 既にある中継は使い続けられる
+        relay.send(b"d", source(50000), target()).await.unwrap();
+        assert_eq!(relay.relays.lock().unwrap().len(), 2);
+    }
+
+    async fn bedrock_responder(extra: &str) -> (BedrockResponder, Arc<MockBackend>) {
+        let (server, backend) = mock_server(&format!(
+            "{extra}\n[servers.bedrock]\nlisten = \"127.0.0.1:0\""
+        ));
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let responder = BedrockResponder {
+            listen: socket.local_addr().unwrap(),
+            relay: UdpRelay::new(Arc::clone(&socket), MAX_BEDROCK_RELAYS),
+            socket,
+            server,
+            guid: 42,
+        };
+        (responder, backend)
+    }
+
+    /// RakNet の Open Connection Request 1
+    fn open_connection() -> Vec<u8> {
+        let mut packet = vec![0x05, 0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE];
+        packet.extend([0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78, 11]);
+        packet
+    }
+
+    #[tokio::test]
+    async fn bedrock_connection_wakes_server() {
+        let (responder, backend) = bedrock_responder("").await;
+        let from = "127.0.0.1:50000".parse().unwrap();
+
+        responder
+            .handle_packet(&open_connection(), from)
+            .await
+            .unwrap();
+
+        assert_eq!(responder.server.lifecycle.state(), State::Starting);
+        wait_for(|| backend.start_count() == 1).await;
+    }
+
+    #[tokio::test]
+    async fn bedrock_connection_does_not_wake_with_allowlist() {
+        let (responder, backend) =
+            bedrock_responder("[servers.allowlist]\nnames = [\"Steve\"]").await;
+        let from = "127.0.0.1:50000".parse().unwrap();
+
+        responder
+            .handle_packet(&open_connection(), from)
+            .await
+            .unwrap();
+
+        assert_eq!(responder.server.lifecycle.state(), State::Stopped);
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(backend.start_count(), 0);
+    }
+}
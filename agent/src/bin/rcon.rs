@@ -0,0 +1,110 @@
+use std::{
+    env,
+    io::{stdout, IsTerminal, Write},
+};
+
+use agent::minecraft::{
+    connection::split_address,
+    raw_json_text::{ColorDepth, RawJsonText},
+    rcon::{self, Rcon},
+};
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+
+/// COMMAND を省略すると対話モードになる。パスワードは環境変数 RCON_PASSWORD でもよい。
+const USAGE: &str = "Usage: [--password PASSWORD] [--no-color] HOST[:PORT] [COMMAND...]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenvy::dotenv().ok();
+
+    let mut args = env::args().skip(1);
+    let mut positional = vec![];
+    let mut password = env::var("RCON_PASSWORD").ok();
+    let mut color = stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--password" => {
+                password =
+                    Some(args.next().ok_or_else(|| {
+                        anyhow::anyhow!("Missing value for --password\n{}", USAGE)
+                    })?)
+            }
+            "--no-color" => color = false,
+            _ => positional.push(arg),
+        }
+    }
+
+    let Some((address, command)) = positional.split_first() else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+    let Some((host, port)) = split_address(address) else {
+        return Err(anyhow::anyhow!("Invalid address: {}", address));
+    };
+    let password = password.ok_or_else(|| anyhow::anyhow!("Password is required\n{}", USAGE))?;
+
+    let mut rcon = Rcon::connect(host, port.unwrap_or(rcon::DEFAULT_PORT), &password).await?;
+    let depth = color.then(ColorDepth::detect);
+
+    if !command.is_empty() {
+        let output = rcon.command(&command.join(" ")).await?;
+        print_output(&output, depth);
+        return Ok(());
+    }
+
+    repl(&mut rcon, depth).await
+}
+
+async fn repl(rcon: &mut Rcon, depth: Option<ColorDepth>) -> anyhow::Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    let mut lines = BufReader::new(stdin()).lines();
+
+    loop {
+        if interactive {
+            print!("> ");
+            stdout().flush()?;
+        }
+
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "exit" | "quit" => break,
+            _ => {}
+        }
+
+        match rcon.command(line).await {
+            Ok(output) => print_output(&output, depth),
+            // 接続が切れたら続けられない
+            Err(e) if e.is_unreachable() => return Err(e.into()),
+            // 長すぎるコマンドなどは入力し直せばよい
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        }
+
+        // サーバが接続を閉じるので、続けても意味がない
+        if line.trim_start_matches('/') == "stop" {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// 出力は `§` の書式コードを含むことがある
+fn print_output(output: &str, depth: Option<ColorDepth>) {
+    if output.is_empty() {
+        return;
+    }
+
+    let text = RawJsonText::from_legacy(output);
+    match depth {
+        Some(depth) => println!("{}", text.to_ansi(depth)),
+        None => println!("{}", text.to_plain()),
+    }
+}
@@ -1,61 +1,80 @@
-use std::{
-    net::{TcpListener, TcpStream},
-    thread,
-};
+use tokio::net::{TcpListener, TcpStream};
 
 use agent::minecraft::{
+    connection::Connection,
+    legacy,
     packet::{
         disconnect_login::DisconnectLogin,
         handshake::Handshake,
-        ping::Ping,
-        read_packet,
-        status_request::StatusRequest,
-        status_response::{self, Players, Version},
-        WritePacketExt,
+        ping::{Ping, Pong},
+        status_response::{Players, StatusResponse, Version},
     },
     raw_json_text::RawJsonText,
 };
 
-fn main() -> anyhow::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:25565")?;
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:25565").await?;
 
-    for stream in listener.incoming() {
-        thread::spawn(|| handle_request(&mut stream?));
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle_request(stream));
     }
-
-    Ok(())
 }
 
-fn handle_request(stream: &mut TcpStream) -> anyhow::Result<()> {
-    let handshake: Handshake = read_packet(stream)?;
+/// どのバージョンのクライアントにも対応しているように見せる
+fn status_response(protocol: i32) -> StatusResponse {
+    StatusResponse {
+        version: Version {
+            name: "Motd Only Server".to_string(),
+            protocol,
+        },
+        players: Players {
+            max: 100,
+            online: 1,
+            sample: None,
+        },
+        description: RawJsonText::String("Hello from Rust!".to_string()),
+        modinfo: None,
+        favicon: None,
+    }
+}
 
-    match handshake.next_status {
-        0x01 => {
-            let _status_request: StatusRequest = read_packet(stream)?;
+async fn handle_request(mut stream: TcpStream) -> anyhow::Result<()> {
+    if legacy::is_legacy_ping(&stream).await? {
+        let ping = legacy::read_ping(&mut stream).await?;
+        let protocol = ping.protocol().unwrap_or(legacy::LEGACY_PROTOCOL);
+        legacy::write_status(&mut stream, &ping, &status_response(protocol)).await?;
+        return Ok(());
+    }
 
-            let status_response = status_response::StatusResponse {
-                version: Version {
-                    name: "Motd Only Server".to_string(),
-                    protocol: 767,
-                },
-                players: Players {
-                    max: 100,
-                    online: 1,
-                    sample: None,
-                },
-                description: RawJsonText::String("Hello from Rust!".to_string()),
-                modinfo: None,
-                favicon: None,
-            };
-            stream.write_packet(status_response)?;
+    let mut conn = Connection::from_stream(stream);
+    let handshake: Handshake = conn.read_packet().await?;
 
-            let ping: Ping = read_packet(stream)?;
-            stream.write_packet(ping)?;
-        }
+    match handshake.next_status {
+        // Status Request と Ping Request はどちらも省略されうる
+        0x01 => loop {
+            let packet = conn.read_raw_packet().await?;
+            match packet.id {
+                0x00 => {
+                    conn.send_packet(status_response(handshake.version)).await?;
+                }
+                0x01 => {
+                    let ping: Ping = packet.decode()?;
+                    conn.send_packet(Pong {
+                        payload: ping.payload,
+                    })
+                    .await?;
+                    break;
+                }
+                id => return Err(anyhow::anyhow!("Unexpected packet in status: {:#04x}", id)),
+            }
+        },
         0x02 => {
-            stream.write_packet(DisconnectLogin {
+            conn.send_packet(DisconnectLogin {
                 reason: RawJsonText::String("Hello!".to_string()),
-            })?;
+            })
+            .await?;
         }
         _ => {
             return Err(anyhow::anyhow!(
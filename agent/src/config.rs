@@ -0,0 +1,731 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    allowlist::{Allowlist, Whitelist},
+    backend::{Backend, Ec2Backend, MockBackend, ProcessBackend},
+    minecraft::{bedrock, connection::split_address, favicon::Favicon, rcon},
+};
+
+// 設定例は proxy.example.toml を参照
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub servers: Vec<ServerConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub name: String,
+    /// プレイヤーが接続するアドレス。複数のサーバで共有できる。
+    pub listen: String,
+    /// このサーバに振り分けるホスト名 (ハンドシェイクの host)
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    /// 同じ listen でどのホスト名にも一致しなかった接続の振り分け先
+    #[serde(default)]
+    pub default: bool,
+    /// 転送先の Minecraft サーバ (`host:port`)
+    pub address: String,
+    pub backend: BackendConfig,
+
+    /// 秒
+    #[serde(default = "default_check_interval")]
+    pub check_interval: u64,
+    /// 秒。この間プレイヤーがいなければ停止する。
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+    /// 秒
+    #[serde(default = "default_boot_timeout")]
+    pub boot_timeout: u64,
+    /// 設定すると、人数の確認にステータスではなく UDP の Query を使う。
+    /// server.properties の `enable-query` と `query.port` に合わせる。
+    /// 起動中にプロキシに来た Query の中継先にもなる (省略時は `address` のポート)。
+    pub query_port: Option<u16>,
+    /// プロキシも listen と同じ UDP ポートで Query に応答する。停止中は状態を返し、
+    /// 起動済みならサーバ (`query_port`) に中継する。UDP にはホスト名が無いので、
+    /// default のサーバか、`hostnames` の無いサーバにだけ設定できる。
+    #[serde(default)]
+    pub answer_query: bool,
+
+    /// 停止中などにサーバ一覧に表示するアイコン (64x64 の PNG)。
+    /// 相対パスは設定ファイルの場所から解決する。
+    pub favicon: Option<PathBuf>,
+    /// 読み込んだ `favicon`
+    #[serde(skip)]
+    pub loaded_favicon: Option<Favicon>,
+
+    /// 停止中のサーバを起動できるプレイヤー。省略すると誰でも起動できる。
+    pub allowlist: Option<AllowlistConfig>,
+    /// 設定すると、停止する前にゲーム内で警告してセーブする
+    pub rcon: Option<RconConfig>,
+    /// 設定すると、Geyser などを通した Bedrock Edition のクライアントも受け付ける
+    pub bedrock: Option<BedrockConfig>,
+
+    #[serde(default)]
+    pub forwarding: Forwarding,
+    #[serde(default)]
+    pub motd: Motd,
+    #[serde(default)]
+    pub messages: Messages,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackendConfig {
+    Ec2 {
+        instance_id: String,
+        region: Option<String>,
+    },
+    Process {
+        command: Vec<String>,
+        /// 相対パスは設定ファイルの場所から解決する。
+        directory: Option<PathBuf>,
+    },
+    Mock,
+}
+
+/// プロキシするときにサーバへ送り直すハンドシェイクの扱い
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Forwarding {
+    /// host/port をプレイヤーが接続した先ではなく転送先のものにする
+    pub rewrite_host: bool,
+    /// Transfer (next_state 3) で来た接続を通常のログインとして送る。
+    /// 無効にするとサーバ側で `accepts-transfers=true` が必要になる。
+    pub transfer_as_login: bool,
+    /// Login Start も読んでプレイヤー名を記録してから送る
+    pub inspect_login: bool,
+}
+
+impl Default for Forwarding {
+    fn default() -> Self {
+        Self {
+            rewrite_host: false,
+            transfer_as_login: true,
+            inspect_login: true,
+        }
+    }
+}
+
+/// 名前か UUID のどれかに一致したプレイヤーだけが起動できる
+///
+/// 名前と UUID はクライアントが認証前に名乗ったものなので偽れる。allowlist にある名前を
+/// 入力すれば誰でも起動できる (EC2 の料金はかかる) ので、うっかり起動されるのを防ぐ程度のもの。
+/// ゲームに参加できるかどうかはサーバ側の online-mode と whitelist で制限する。
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AllowlistConfig {
+    /// 大文字小文字は区別しない
+    pub names: Vec<String>,
+    pub uuids: Vec<Uuid>,
+    /// サーバの whitelist.json。更新されるたびに読み直す。
+    /// 相対パスは設定ファイルの場所から解決する。
+    pub whitelist: Option<PathBuf>,
+    /// 読み込んだ `whitelist`。以降の更新は `Allowlist` が読み直す。
+    #[serde(skip)]
+    pub loaded_whitelist: Option<Whitelist>,
+}
+
+/// サーバの server.properties の `enable-rcon` などと合わせる
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RconConfig {
+    /// ホストは address と同じ
+    #[serde(default = "default_rcon_port")]
+    pub port: u16,
+    pub password: String,
+    /// 停止までの残り秒数。この順に警告する。
+    #[serde(default = "default_countdown")]
+    pub countdown: Vec<u64>,
+}
+
+/// Bedrock Edition (RakNet) の待ち受けと転送先
+///
+/// RakNet にはホスト名が無いので、listen は他のサーバと共有できない。
+/// 接続時にプレイヤー名が分からないため、allowlist を設定したサーバは Bedrock からは起動しない。
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BedrockConfig {
+    /// UDP で待ち受けるアドレス
+    pub listen: String,
+    /// 転送先のポート (Geyser の `bedrock.port`)。ホストは address と同じ。
+    #[serde(default = "default_bedrock_port")]
+    pub port: u16,
+}
+
+/// サーバ一覧に表示する説明文。`{elapsed}` は経過時間に置き換わる。
+/// `&a` や `&#RRGGBB` などの書式コードが使える。
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Motd {
+    pub stopped: String,
+    pub starting: String,
+    pub running: String,
+    pub stopping: String,
+    pub failed: String,
+}
+
+impl Default for Motd {
+    fn default() -> Self {
+        Self {
+            stopped: "スリープ中 - 接続すると起動します".to_string(),
+            starting: "起動中 (経過 {elapsed})".to_string(),
+            running: "起動済み - 再度接続してください".to_string(),
+            stopping: "停止中 - しばらくお待ちください".to_string(),
+            failed: "起動に失敗しました - 接続すると再試行します".to_string(),
+        }
+    }
+}
+
+/// ログイン時の切断理由と Limbo での表示。`{elapsed}` は経過時間に置き換わる。
+/// `&a` や `&#RRGGBB` などの書式コードが使える。
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Messages {
+    pub waking: String,
+    pub starting: String,
+    pub running: String,
+    pub stopping: String,
+    pub failed: String,
+    /// allowlist に無いプレイヤーが停止中に接続したとき
+    pub not_allowed: String,
+
+    /// Limbo は 1.20.5 - 1.21.1 (プロトコル 766, 767) のクライアントだけが対象。
+    /// それ以外のバージョンでは起動を始めて上の理由で切断する。
+    pub limbo_title: String,
+    pub limbo_subtitle: String,
+    pub limbo_starting: String,
+    pub limbo_stopping: String,
+    pub limbo_waiting: String,
+
+    /// `{remaining}` は停止までの秒数
+    pub shutdown_warning: String,
+    pub shutdown_cancelled: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            waking: "サーバを起動しています。しばらくしてから再度接続してください。".to_string(),
+            starting: "サーバは起動処理中です。しばらくしてから再度接続してください。".to_string(),
+            running: "サーバは起動済みです。再度接続してください。".to_string(),
+            stopping: "サーバは停止処理中です。停止後に再度接続してください。".to_string(),
+            failed: "サーバを起動できませんでした。後ほど試してください。".to_string(),
+            not_allowed: "&eこのサーバを起動できるのは登録されたプレイヤーだけです。\n&7参加したい場合は管理者に連絡してください。".to_string(),
+
+            limbo_title: "サーバを起動しています".to_string(),
+            limbo_subtitle: "準備ができたら自動で接続します".to_string(),
+            limbo_starting: "起動中... {elapsed}".to_string(),
+            limbo_stopping: "停止処理が終わるのを待っています...".to_string(),
+            limbo_waiting: "起動を待っています...".to_string(),
+
+            shutdown_warning: "&eプレイヤーがいないため、&c{remaining}&e 秒後にサーバを停止します".to_string(),
+            shutdown_cancelled: "&aプレイヤーが参加したため、停止を取りやめました".to_string(),
+        }
+    }
+}
+
+fn default_check_interval() -> u64 {
+    60 * 5
+}
+
+fn default_idle_timeout() -> u64 {
+    60 * 15
+}
+
+fn default_boot_timeout() -> u64 {
+    60 * 10
+}
+
+fn default_rcon_port() -> u16 {
+    rcon::DEFAULT_PORT
+}
+
+fn default_bedrock_port() -> u16 {
+    bedrock::DEFAULT_PORT
+}
+
+fn default_countdown() -> Vec<u64> {
+    vec![60, 30, 10, 5, 4, 3, 2, 1]
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    /// 問題のあるキー (`servers[0].listen` など) とその理由
+    Invalid {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid { key, message } => write!(f, "{}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Default)]
+struct Route<'a> {
+    count: usize,
+    hostnames: HashMap<String, &'a str>,
+    default: Option<&'a str>,
+    catch_all: Vec<usize>,
+    /// default でないのに `answer_query` を設定したサーバ
+    answer_query: Vec<usize>,
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.into(),
+        message: message.into(),
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let s = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        Self::parse_in(&s, path.parent())
+    }
+
+    pub fn parse(s: &str) -> Result<Self, ConfigError> {
+        Self::parse_in(s, None)
+    }
+
+    /// `base` は相対パスを解決するディレクトリ
+    fn parse_in(s: &str, base: Option<&Path>) -> Result<Self, ConfigError> {
+        let de = toml::Deserializer::parse(s).map_err(|e| invalid("(syntax)", e.to_string()))?;
+        let mut config: Config = serde_path_to_error::deserialize(de).map_err(|e| {
+            let key = e.path().to_string();
+            invalid(key, e.inner().message())
+        })?;
+
+        if let Some(base) = base {
+            for server in &mut config.servers {
+                if let Some(favicon) = &mut server.favicon {
+                    *favicon = base.join(&*favicon);
+                }
+                if let Some(whitelist) = server
+                    .allowlist
+                    .as_mut()
+                    .and_then(|allowlist| allowlist.whitelist.as_mut())
+                {
+                    *whitelist = base.join(&*whitelist);
+                }
+                if let BackendConfig::Process {
+                    directory: Some(directory),
+                    ..
+                } = &mut server.backend
+                {
+                    *directory = base.join(&*directory);
+                }
+            }
+        }
+
+        config.validate()?;
+        config.load_files()?;
+
+        Ok(config)
+    }
+
+    /// 設定から参照しているファイルを読み込んで、結果を設定に残す
+    fn load_files(&mut self) -> Result<(), ConfigError> {
+        for (i, server) in self.servers.iter_mut().enumerate() {
+            let key = |field: &str| format!("servers[{}].{}", i, field);
+
+            if let Some(path) = &server.favicon {
+                let favicon = Favicon::load(path)
+                    .map_err(|e| invalid(key("favicon"), format!("{}: {}", path.display(), e)))?;
+                server.loaded_favicon = Some(favicon);
+            }
+
+            if let Some(allowlist) = &mut server.allowlist {
+                if let Some(path) = &allowlist.whitelist {
+                    let whitelist = Whitelist::load(path).map_err(|e| {
+                        invalid(
+                            key("allowlist.whitelist"),
+                            format!("{}: {}", path.display(), e),
+                        )
+                    })?;
+                    allowlist.loaded_whitelist = Some(whitelist);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.servers.is_empty() {
+            return Err(invalid("servers", "at least one server is required"));
+        }
+
+        let mut names = HashSet::new();
+        let mut routes: HashMap<SocketAddr, Route> = HashMap::new();
+        let mut bedrock_listens: HashMap<SocketAddr, &str> = HashMap::new();
+        for (i, server) in self.servers.iter().enumerate() {
+            let key = |field: &str| format!("servers[{}].{}", i, field);
+
+            if server.name.is_empty() {
+                return Err(invalid(key("name"), "must not be empty"));
+            }
+            if !names.insert(&server.name) {
+                return Err(invalid(
+                    key("name"),
+                    format!("duplicate name `{}`", server.name),
+                ));
+            }
+
+            let listen: SocketAddr = server
+                .listen
+                .parse()
+                .map_err(|_| invalid(key("listen"), "expected `ip:port`"))?;
+
+            let route = routes.entry(listen).or_default();
+            route.count += 1;
+            for hostname in &server.hostnames {
+                if hostname.is_empty() {
+                    return Err(invalid(key("hostnames"), "must not contain an empty name"));
+                }
+                if let Some(other) = route
+                    .hostnames
+                    .insert(hostname.to_ascii_lowercase(), &server.name)
+                {
+                    return Err(invalid(
+                        key("hostnames"),
+                        format!("`{}` is already routed to `{}`", hostname, other),
+                    ));
+                }
+            }
+            if server.default {
+                if let Some(other) = route.default.replace(&server.name) {
+                    return Err(invalid(
+                        key("default"),
+                        format!("`{}` already has a default server `{}`", listen, other),
+                    ));
+                }
+            }
+            if server.hostnames.is_empty() && !server.default {
+                route.catch_all.push(i);
+            }
+            if server.answer_query && !server.default {
+                route.answer_query.push(i);
+            }
+
+            if !matches!(split_address(&server.address), Some((_, Some(_)))) {
+                return Err(invalid(key("address"), "expected `host:port`"));
+            }
+
+            match &server.backend {
+                BackendConfig::Ec2 { instance_id, .. } if !instance_id.starts_with("i-") => {
+                    return Err(invalid(
+                        key("backend.instance_id"),
+                        "expected an EC2 instance id (`i-...`)",
+                    ));
+                }
+                BackendConfig::Process { command, .. } if command.is_empty() => {
+                    return Err(invalid(key("backend.command"), "must not be empty"));
+                }
+                _ => {}
+            }
+
+            if let Some(rcon) = &server.rcon {
+                if rcon.password.is_empty() {
+                    return Err(invalid(key("rcon.password"), "must not be empty"));
+                }
+                if !rcon.countdown.windows(2).all(|w| w[0] > w[1]) {
+                    return Err(invalid(
+                        key("rcon.countdown"),
+                        "must be in strictly decreasing order",
+                    ));
+                }
+            }
+
+            if let Some(bedrock) = &server.bedrock {
+                let listen: SocketAddr = bedrock
+                    .listen
+                    .parse()
+                    .map_err(|_| invalid(key("bedrock.listen"), "expected `ip:port`"))?;
+                if let Some(other) = bedrock_listens.insert(listen, &server.name) {
+                    return Err(invalid(
+                        key("bedrock.listen"),
+                        format!("`{}` is already used by `{}`", listen, other),
+                    ));
+                }
+            }
+
+            if server.check_interval == 0 {
+                return Err(invalid(key("check_interval"), "must be greater than 0"));
+            }
+            if server.idle_timeout < server.check_interval {
+                return Err(invalid(
+                    key("idle_timeout"),
+                    "must not be shorter than check_interval",
+                ));
+            }
+            if server.boot_timeout == 0 {
+                return Err(invalid(key("boot_timeout"), "must be greater than 0"));
+            }
+        }
+
+        for route in routes.values() {
+            // 同じ listen を共有するなら、ホスト名か default で区別できなければならない
+            if 2 <= route.count {
+                if let Some(&i) = route.catch_all.first() {
+                    return Err(invalid(
+                        format!("servers[{}].hostnames", i),
+                        format!(
+                            "required when several servers share `{}` (or set `default = true`)",
+                            self.servers[i].listen
+                        ),
+                    ));
+                }
+            }
+
+            // UDP にはホスト名が無いので、Query にはホスト名なしで振り分けられるサーバだけが応答できる
+            for &i in &route.answer_query {
+                if route.default.is_some() || route.catch_all.first() != Some(&i) {
+                    return Err(invalid(
+                        format!("servers[{}].answer_query", i),
+                        format!(
+                            "requires `default = true` (or no `hostnames`) on `{}`",
+                            self.servers[i].listen
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ServerConfig {
+    pub fn backend(&self) -> anyhow::Result<Arc<dyn Backend>> {
+        let backend: Arc<dyn Backend> = match &self.backend {
+            BackendConfig::Ec2 {
+                instance_id,
+                region,
+            } => {
+                let mut backend = Ec2Backend::new(instance_id).with_address(&self.address);
+                if let Some(region) = region {
+                    backend = backend.with_region(region);
+                }
+                Arc::new(backend)
+            }
+            BackendConfig::Process { command, directory } => Arc::new(ProcessBackend::new(
+                command,
+                directory.clone(),
+                &self.address,
+            )?),
+            BackendConfig::Mock => Arc::new(MockBackend::new(&self.address)),
+        };
+
+        Ok(backend)
+    }
+
+    pub fn allowlist(&self) -> Option<Allowlist> {
+        self.allowlist.as_ref().map(Allowlist::new)
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval)
+    }
+
+    pub fn boot_timeout(&self) -> Duration {
+        Duration::from_secs(self.boot_timeout)
+    }
+
+    /// 停止するまでに連続で無人だった回数
+    pub fn idle_checks(&self) -> u64 {
+        self.idle_timeout / self.check_interval
+    }
+}
+
+/// `{elapsed}` などのプレースホルダを置き換える
+pub fn render(template: &str, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    template.replace("{elapsed}", &format!("{}:{:02}", secs / 60, secs % 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 検証に失敗したキー
+    fn error_key(s: &str) -> String {
+        match Config::parse(s) {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            result => panic!("expected an invalid config, got {:?}", result),
+        }
+    }
+
+    /// 127.0.0.1:25565 で待ち受けるモックのサーバ
+    fn server(name: &str, extra: &str) -> String {
+        format!(
+            r#"
+            [[servers]]
+            name = "{name}"
+            listen = "127.0.0.1:25565"
+            address = "127.0.0.1:1"
+            {extra}
+
+            [servers.backend]
+            kind = "mock"
+            "#
+        )
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let config = server("a", "") + &server("a", "hostnames = [\"a.example.com\"]");
+        assert_eq!(error_key(&config), "servers[1].name");
+    }
+
+    #[test]
+    fn rejects_bad_listen() {
+        let config = server("a", "").replace("127.0.0.1:25565", "localhost");
+        assert_eq!(error_key(&config), "servers[0].listen");
+    }
+
+    #[test]
+    fn routes_on_shared_listen_must_be_distinguishable() {
+        let a = server("a", "hostnames = [\"a.example.com\"]");
+
+        let b = server("b", "hostnames = [\"A.example.com\"]");
+        assert_eq!(error_key(&(a.clone() + &b)), "servers[1].hostnames");
+
+        let b = server("b", "hostnames = [\"b.example.com\"]\ndefault = true");
+        let c = server("c", "default = true");
+        assert_eq!(error_key(&(a.clone() + &b + &c)), "servers[2].default");
+
+        let b = server("b", "");
+        assert_eq!(error_key(&(b.clone() + &a)), "servers[0].hostnames");
+        assert_eq!(error_key(&(a.clone() + &b)), "servers[1].hostnames");
+
+        let b = server("b", "default = true");
+        assert!(Config::parse(&(a.clone() + &b)).is_ok());
+        // 別の listen ならホスト名は要らない
+        let b = server("b", "").replace("25565", "25566");
+        assert!(Config::parse(&(a + &b)).is_ok());
+    }
+
+    #[test]
+    fn rejects_ec2_instance_id_without_prefix() {
+        let config = |instance_id: &str| {
+            server("a", "").replace(
+                "kind = \"mock\"",
+                &format!("kind = \"ec2\"\ninstance_id = \"{instance_id}\""),
+            )
+        };
+        assert!(Config::parse(&config("i-0123456789abcdef0")).is_ok());
+        assert_eq!(
+            error_key(&config("0123456789abcdef0")),
+            "servers[0].backend.instance_id"
+        );
+    }
+
+    #[test]
+    fn countdown_must_decrease() {
+        let config = |countdown: &str| {
+            server(
+                "a",
+                &format!("rcon = {{ password = \"p\", countdown = {countdown} }}"),
+            )
+        };
+        assert!(Config::parse(&config("[60, 10, 5]")).is_ok());
+        assert_eq!(error_key(&config("[60, 60]")), "servers[0].rcon.countdown");
+        assert_eq!(error_key(&config("[5, 10]")), "servers[0].rcon.countdown");
+    }
+
+    #[test]
+    fn type_errors_report_key_path() {
+        let config = server("a", "check_interval = \"soon\"");
+        assert_eq!(error_key(&config), "servers[0].check_interval");
+
+        let config = server("a", "").replace("kind = \"mock\"", "kind = \"docker\"");
+        assert_eq!(error_key(&config), "servers[0].backend.kind");
+    }
+
+    #[test]
+    fn process_directory_is_relative_to_config_file() {
+        let config = |directory: &str| {
+            let config = server("a", "").replace(
+                "kind = \"mock\"",
+                &format!("kind = \"process\"\ncommand = [\"java\"]\ndirectory = \"{directory}\""),
+            );
+            let config = Config::parse_in(&config, Some(Path::new("/etc/agent"))).unwrap();
+            match &config.servers[0].backend {
+                BackendConfig::Process { directory, .. } => directory.clone().unwrap(),
+                backend => panic!("unexpected backend {:?}", backend),
+            }
+        };
+
+        assert_eq!(
+            config("servers/creative"),
+            Path::new("/etc/agent/servers/creative")
+        );
+        assert_eq!(config("/srv/creative"), Path::new("/srv/creative"));
+    }
+
+    #[test]
+    fn answer_query_needs_a_server_routed_without_hostname() {
+        let config = |first: &str, second: &str| {
+            format!(
+                r#"
+                [[servers]]
+                name = "a"
+                listen = "127.0.0.1:25565"
+                address = "127.0.0.1:1"
+                answer_query = true
+                backend.kind = "mock"
+                {first}
+                {second}
+                "#
+            )
+        };
+        let other = r#"
+            [[servers]]
+            name = "b"
+            listen = "127.0.0.1:25565"
+            hostnames = ["b.example.com"]
+            address = "127.0.0.1:2"
+            backend.kind = "mock"
+        "#;
+
+        assert!(Config::parse(&config("", "")).is_ok());
+        assert!(Config::parse(&config("default = true", other)).is_ok());
+        assert!(Config::parse(&config(
+            "hostnames = [\"a.example.com\"]\ndefault = true",
+            ""
+        ))
+        .is_ok());
+
+        // 1 台だけでも、ホスト名でしか振り分けられなければ応答できない
+        assert_eq!(
+            error_key(&config("hostnames = [\"a.example.com\"]", "")),
+            "servers[0].answer_query"
+        );
+        assert_eq!(
+            error_key(&config("hostnames = [\"a.example.com\"]", other)),
+            "servers[0].answer_query"
+        );
+    }
+}
@@ -1 +1,6 @@
+pub mod allowlist;
+pub mod backend;
+pub mod config;
+pub mod lifecycle;
+pub mod limbo;
 pub mod minecraft;
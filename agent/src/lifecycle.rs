@@ -0,0 +1,128 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+const HISTORY_LIMIT: usize = 32;
+
+/// プロキシから見た管理対象サーバの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+    Failed,
+}
+
+impl State {
+    pub fn can_transition_to(self, to: State) -> bool {
+        use State::*;
+
+        matches!(
+            (self, to),
+            (Stopped | Failed, Starting)
+                | (Starting, Running | Failed)
+                | (Running, Stopping | Failed)
+                | (Stopping, Stopped | Failed)
+                | (Failed, Stopped)
+        )
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            State::Stopped => "stopped",
+            State::Starting => "starting",
+            State::Running => "running",
+            State::Stopping => "stopping",
+            State::Failed => "failed",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: State,
+    pub to: State,
+    pub at: SystemTime,
+    pub reason: String,
+}
+
+struct Inner {
+    state: State,
+    since: Instant,
+    history: VecDeque<Transition>,
+}
+
+/// 状態遷移を一箇所で管理する
+///
+/// 遷移は `transition` でのみ行い、許されない遷移はエラーになる。
+/// 判定と書き換えを同じロックの中で行うので、同時に来たログインのうち
+/// `Starting` に遷移できるのは一つだけになる。
+pub struct Lifecycle {
+    inner: Mutex<Inner>,
+}
+
+impl Lifecycle {
+    pub fn new(state: State) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                state,
+                since: Instant::now(),
+                history: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.inner.lock().unwrap().state
+    }
+
+    /// 現在の状態になってからの経過時間
+    pub fn elapsed(&self) -> Duration {
+        self.inner.lock().unwrap().since.elapsed()
+    }
+
+    pub fn last_transition(&self) -> Option<Transition> {
+        self.inner.lock().unwrap().history.back().cloned()
+    }
+
+    pub fn history(&self) -> Vec<Transition> {
+        self.inner.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    pub fn transition(&self, to: State, reason: &str) -> anyhow::Result<Transition> {
+        let mut inner = self.inner.lock().unwrap();
+        let from = inner.state;
+        if !from.can_transition_to(to) {
+            return Err(anyhow::anyhow!("Invalid transition: {} -> {}", from, to));
+        }
+
+        let transition = Transition {
+            from,
+            to,
+            at: SystemTime::now(),
+            reason: reason.to_string(),
+        };
+
+        inner.state = to;
+        inner.since = Instant::now();
+        if inner.history.len() == HISTORY_LIMIT {
+            inner.history.pop_front();
+        }
+        inner.history.push_back(transition.clone());
+
+        Ok(transition)
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new(State::Stopped)
+    }
+}
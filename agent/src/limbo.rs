@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use tokio::time::{self, Instant};
+
+use crate::minecraft::{
+    connection::Connection,
+    packet::{
+        configuration::{FinishConfiguration, KnownPack, KnownPacks, RegistryData},
+        login_start::LoginStart,
+        login_success::LoginSuccess,
+        play::{
+            Disconnect, GameEvent, KeepAlive, Login, SetActionBarText, SetSubtitleText,
+            SetTitleAnimationTimes, SetTitleText, SynchronizePlayerPosition, Transfer,
+        },
+        Versioned,
+    },
+    raw_json_text::RawJsonText,
+    version,
+};
+
+mod registry;
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+const LOGIN_ACKNOWLEDGED: u32 = 0x03;
+const SERVERBOUND_KNOWN_PACKS: u32 = 0x07;
+const ACKNOWLEDGE_FINISH_CONFIGURATION: u32 = 0x03;
+
+/// ワールドを持たない待機場所
+///
+/// ログインを最後まで通して、何もない空間にスペクテイターとして置いておく。
+/// キープアライブを送り続けるのでクライアントはタイムアウトしない。
+pub struct Limbo {
+    conn: Connection,
+    last_keep_alive: Instant,
+}
+
+impl Limbo {
+    /// Limbo が話せるプロトコル (1.20.5 - 1.21.1)
+    ///
+    /// 登録するレジストリやパケット ID がバージョンごとに違うので、これ以外のクライアントは
+    /// Limbo に入れず、これまでどおり切断理由で知らせる。
+    pub fn supports(protocol: i32) -> bool {
+        (version::V1_20_5..=version::V1_21).contains(&protocol)
+    }
+
+    pub async fn join(
+        mut conn: Connection,
+        protocol: i32,
+        login_start: &LoginStart,
+    ) -> anyhow::Result<Self> {
+        // 1.20.2 以降のクライアントは必ず UUID を送ってくる
+        let login_success = LoginSuccess {
+            uuid: login_start.uuid.unwrap_or_else(uuid::Uuid::new_v4),
+            username: login_start.name.clone(),
+            properties: vec![],
+        };
+        conn.send_packet(Versioned(login_success, protocol)).await?;
+        wait_for(&mut conn, LOGIN_ACKNOWLEDGED).await?;
+
+        conn.send_packet(KnownPacks {
+            packs: registry::known_pack_versions(protocol)
+                .iter()
+                .map(|version| KnownPack {
+                    namespace: "minecraft".to_string(),
+                    id: "core".to_string(),
+                    version: version.to_string(),
+                })
+                .collect(),
+        })
+        .await?;
+        wait_for(&mut conn, SERVERBOUND_KNOWN_PACKS).await?;
+
+        for (registry, entries) in registry::registries(protocol) {
+            conn.send_packet(RegistryData {
+                registry: registry.to_string(),
+                entries: entries.iter().map(|e| format!("minecraft:{}", e)).collect(),
+            })
+            .await?;
+        }
+
+        conn.send_packet(FinishConfiguration {}).await?;
+        wait_for(&mut conn, ACKNOWLEDGE_FINISH_CONFIGURATION).await?;
+
+        conn.send_packet(Login {
+            entity_id: 1,
+            dimension_type: 0,
+            dimension_name: "minecraft:overworld".to_string(),
+            view_distance: 2,
+            game_mode: 3,
+        })
+        .await?;
+        // ワールドの高さの外に置くと、チャンクを送らなくても読み込み画面が閉じる
+        conn.send_packet(SynchronizePlayerPosition {
+            x: 0.0,
+            y: 400.0,
+            z: 0.0,
+            teleport_id: 1,
+        })
+        .await?;
+        conn.send_packet(GameEvent {
+            event: GameEvent::START_WAITING_FOR_LEVEL_CHUNKS,
+            value: 0.0,
+        })
+        .await?;
+
+        Ok(Self {
+            conn,
+            last_keep_alive: Instant::now(),
+        })
+    }
+
+    /// クライアントからのパケットを読み捨てながら待つ。切断されたら `false`。
+    pub async fn idle(&mut self, duration: Duration) -> bool {
+        let deadline = time::sleep(duration);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return true,
+                packet = self.conn.read_raw_packet() => {
+                    if packet.is_err() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn title(&mut self, title: RawJsonText, subtitle: RawJsonText) -> anyhow::Result<()> {
+        self.conn
+            .send_packet(SetTitleAnimationTimes {
+                fade_in: 10,
+                stay: 20 * 60 * 10,
+                fade_out: 10,
+            })
+            .await?;
+        self.conn
+            .send_packet(SetSubtitleText { text: subtitle })
+            .await?;
+        self.conn.send_packet(SetTitleText { text: title }).await?;
+
+        Ok(())
+    }
+
+    /// アクションバーを更新し、必要ならキープアライブを送る
+    pub async fn tick(&mut self, action_bar: RawJsonText) -> anyhow::Result<()> {
+        self.conn
+            .send_packet(SetActionBarText { text: action_bar })
+            .await?;
+
+        if KEEP_ALIVE_INTERVAL <= self.last_keep_alive.elapsed() {
+            let id = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_millis() as i64;
+            self.conn.send_packet(KeepAlive { id }).await?;
+            self.last_keep_alive = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    pub async fn transfer(mut self, host: &str, port: u16) -> anyhow::Result<()> {
+        self.conn
+            .send_packet(Transfer {
+                host: host.to_string(),
+                port,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn disconnect(mut self, reason: RawJsonText) -> anyhow::Result<()> {
+        self.conn.send_packet(Disconnect { reason }).await?;
+
+        Ok(())
+    }
+}
+
+/// 指定したパケットが来るまで読み飛ばす
+async fn wait_for(conn: &mut Connection, packet_id: u32) -> anyhow::Result<()> {
+    loop {
+        if conn.read_raw_packet().await?.id == packet_id {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+// クライアント側の minecraft:core に含まれるエントリ
+// Known Pack を使うのでデータ本体は送らないが、ID の一覧は送る必要がある
+// 型ごとに最低限必要なものだけを並べている (damage_type は全件必要)
+
+use crate::minecraft::version;
+
+/// minecraft:core のバージョン。クライアントは自分のバージョンと一致するものだけを選ぶ。
+pub fn known_pack_versions(protocol: i32) -> &'static [&'static str] {
+    if protocol >= version::V1_21 {
+        &["1.21", "1.21.1"]
+    } else {
+        &["1.20.5", "1.20.6"]
+    }
+}
+
+/// `protocol` のクライアントに送るレジストリ
+pub fn registries(protocol: i32) -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut registries: Vec<_> = REGISTRIES_1_20_5
+        .iter()
+        .map(|(registry, entries)| (*registry, entries.to_vec()))
+        .collect();
+    if protocol < version::V1_21 {
+        return registries;
+    }
+
+    for (registry, added) in ADDED_IN_1_21 {
+        match registries.iter_mut().find(|(r, _)| r == registry) {
+            Some((_, entries)) => {
+                entries.extend_from_slice(added);
+                entries.sort_unstable();
+            }
+            None => registries.push((registry, added.to_vec())),
+        }
+    }
+
+    registries
+}
+
+const REGISTRIES_1_20_5: &[(&str, &[&str])] = &[
+    (
+        "minecraft:dimension_type",
+        &["overworld", "overworld_caves", "the_end", "the_nether"],
+    ),
+    ("minecraft:worldgen/biome", &["plains", "the_void"]),
+    (
+        "minecraft:chat_type",
+        &[
+            "chat",
+            "emote_command",
+            "msg_command_incoming",
+            "msg_command_outgoing",
+            "say_command",
+            "team_msg_command_incoming",
+            "team_msg_command_outgoing",
+        ],
+    ),
+    (
+        "minecraft:damage_type",
+        &[
+            "arrow",
+            "bad_respawn_point",
+            "cactus",
+            "cramming",
+            "dragon_breath",
+            "drown",
+            "dry_out",
+            "explosion",
+            "fall",
+            "falling_anvil",
+            "falling_block",
+            "falling_stalactite",
+            "fireball",
+            "fireworks",
+            "fly_into_wall",
+            "freeze",
+            "generic",
+            "generic_kill",
+            "hot_floor",
+            "in_fire",
+            "in_wall",
+            "indirect_magic",
+            "lava",
+            "lightning_bolt",
+            "mace_smash",
+            "magic",
+            "mob_attack",
+            "mob_attack_no_aggro",
+            "mob_projectile",
+            "on_fire",
+            "out_of_world",
+            "outside_border",
+            "player_attack",
+            "player_explosion",
+            "sonic_boom",
+            "spit",
+            "stalagmite",
+            "starve",
+            "sting",
+            "sweet_berry_bush",
+            "thorns",
+            "thrown",
+            "trident",
+            "unattributed_fireball",
+            "wind_charge",
+            "wither",
+            "wither_skull",
+        ],
+    ),
+    (
+        "minecraft:trim_pattern",
+        &[
+            "coast",
+            "dune",
+            "eye",
+            "host",
+            "raiser",
+            "rib",
+            "sentry",
+            "shaper",
+            "silence",
+            "snout",
+            "spire",
+            "tide",
+            "vex",
+            "ward",
+            "wayfinder",
+            "wild",
+        ],
+    ),
+    (
+        "minecraft:trim_material",
+        &[
+            "amethyst",
+            "copper",
+            "diamond",
+            "emerald",
+            "gold",
+            "iron",
+            "lapis",
+            "netherite",
+            "quartz",
+            "redstone",
+        ],
+    ),
+    (
+        "minecraft:wolf_variant",
+        &[
+            "ashen", "black", "chestnut", "pale", "rusty", "snowy", "spotted", "striped", "woods",
+        ],
+    ),
+    (
+        "minecraft:banner_pattern",
+        &["base", "border", "bricks", "creeper"],
+    ),
+];
+
+/// 1.21 で増えたもの (実験的機能だった要素と、データ駆動になったレジストリ)
+const ADDED_IN_1_21: &[(&str, &[&str])] = &[
+    ("minecraft:trim_pattern", &["bolt", "flow"]),
+    (
+        "minecraft:painting_variant",
+        &[
+            "alban",
+            "aztec",
+            "aztec2",
+            "bomb",
+            "kebab",
+            "plant",
+            "wasteland",
+        ],
+    ),
+    (
+        "minecraft:enchantment",
+        &[
+            "aqua_affinity",
+            "bane_of_arthropods",
+            "binding_curse",
+            "blast_protection",
+            "breach",
+            "channeling",
+            "density",
+            "depth_strider",
+            "efficiency",
+            "feather_falling",
+            "fire_aspect",
+            "fire_protection",
+            "flame",
+            "fortune",
+            "frost_walker",
+            "impaling",
+            "infinity",
+            "knockback",
+            "looting",
+            "loyalty",
+            "luck_of_the_sea",
+            "lure",
+            "mending",
+            "multishot",
+            "piercing",
+            "power",
+            "projectile_protection",
+            "protection",
+            "punch",
+            "quick_charge",
+            "respiration",
+            "riptide",
+            "sharpness",
+            "silk_touch",
+            "smite",
+            "soul_speed",
+            "sweeping_edge",
+            "swift_sneak",
+            "thorns",
+            "unbreaking",
+            "vanishing_curse",
+            "wind_burst",
+        ],
+    ),
+    (
+        "minecraft:jukebox_song",
+        &[
+            "11",
+            "13",
+            "5",
+            "blocks",
+            "cat",
+            "chirp",
+            "creator",
+            "creator_music_box",
+            "far",
+            "mall",
+            "mellohi",
+            "otherside",
+            "pigstep",
+            "precipice",
+            "relic",
+            "stal",
+            "strad",
+            "wait",
+            "ward",
+        ],
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(protocol: i32, registry: &str) -> Option<Vec<&'static str>> {
+        registries(protocol)
+            .into_iter()
+            .find(|(r, _)| *r == registry)
+            .map(|(_, entries)| entries)
+    }
+
+    #[test]
+    fn registries_for_1_20_5_leave_out_1_21_additions() {
+        assert_eq!(entries(version::V1_20_5, "minecraft:enchantment"), None);
+        assert_eq!(
+            entries(version::V1_20_5, "minecraft:painting_variant"),
+            None
+        );
+        assert_eq!(entries(version::V1_20_5, "minecraft:jukebox_song"), None);
+
+        let trims = entries(version::V1_20_5, "minecraft:trim_pattern").unwrap();
+        assert!(!trims.contains(&"bolt"));
+        assert_eq!(known_pack_versions(version::V1_20_5), ["1.20.5", "1.20.6"]);
+    }
+
+    #[test]
+    fn registries_for_1_21_include_additions() {
+        assert!(entries(version::V1_21, "minecraft:enchantment").is_some());
+        let trims = entries(version::V1_21, "minecraft:trim_pattern").unwrap();
+        assert_eq!(trims.len(), 18);
+        assert!(trims.is_sorted());
+        assert_eq!(
+            registries(version::V1_21).len(),
+            registries(version::V1_20_5).len() + 3
+        );
+        assert_eq!(known_pack_versions(version::V1_21), ["1.21", "1.21.1"]);
+    }
+}
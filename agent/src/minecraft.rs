@@ -1,5 +1,16 @@
+pub mod bedrock;
 pub mod client;
+pub mod codec;
+pub mod connection;
+pub mod dns;
+mod error;
+pub mod favicon;
+pub mod legacy;
+pub mod nbt;
 pub mod packet;
+pub mod query;
 pub mod raw_json_text;
+pub mod rcon;
+pub mod version;
 
-mod connection;
+pub use error::{Error, Result};
@@ -0,0 +1,312 @@
+//! Bedrock Edition の RakNet Unconnected Ping/Pong
+//!
+//! https://minecraft.wiki/w/RakNet
+//!
+//! Geyser や BDS は UDP の 19132 番で応答する。接続しなくてもサーバ一覧に表示する
+//! MOTD や人数が分かる。応答する側が使う [`Request`] などもある。
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::{net::UdpSocket, time};
+
+use super::{query, Error, Result};
+
+pub const DEFAULT_PORT: u16 = 19132;
+
+/// 停止中に返す Pong のプロトコル番号とバージョン。表示に使われるだけで、
+/// 違っていてもクライアントは接続を試みる。
+pub const PROTOCOL: i32 = 827;
+pub const VERSION: &str = "1.21.100";
+
+const UNCONNECTED_PING: u8 = 0x01;
+/// 空きのあるサーバだけが応答する Ping。プロキシはどちらにも応答する。
+const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+const UNCONNECTED_PONG: u8 = 0x1C;
+/// 接続前のパケットに必ず含まれる 16 バイト
+const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Pong に載っている `MCPE;MOTD;...;` の中身
+#[derive(Debug, Clone, Serialize)]
+pub struct BedrockStatus {
+    /// `MCPE` (教育版は `MCEE`)
+    pub edition: String,
+    pub motd: String,
+    pub protocol: i32,
+    pub version: String,
+    pub online_players: usize,
+    pub max_players: usize,
+    pub server_guid: i64,
+    /// 2 行目。ワールド名が入っていることが多い。
+    pub sub_motd: String,
+    pub game_mode: String,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+}
+
+impl BedrockStatus {
+    /// 古いサーバは後ろのフィールドを省略する
+    pub fn parse(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split(';').collect();
+        if fields.len() < 6 {
+            return Err(Error::protocol(format!("invalid bedrock server id: {}", s)));
+        }
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        let number = |i: usize| {
+            field(i).parse::<usize>().map_err(|_| {
+                Error::protocol(format!("invalid number in bedrock server id: {}", field(i)))
+            })
+        };
+
+        Ok(Self {
+            edition: field(0).to_string(),
+            motd: field(1).to_string(),
+            protocol: field(2).parse().unwrap_or(0),
+            version: field(3).to_string(),
+            online_players: number(4)?,
+            max_players: number(5)?,
+            server_guid: field(6).parse().unwrap_or(0),
+            sub_motd: field(7).to_string(),
+            game_mode: field(8).to_string(),
+            port_v4: field(10).parse().ok(),
+            port_v6: field(11).parse().ok(),
+        })
+    }
+
+    /// `;` は区切りなので MOTD に入れられない
+    pub fn to_server_id(&self) -> String {
+        let port = |port: Option<u16>| port.map(|p| p.to_string()).unwrap_or_default();
+        let game_mode_id = match self.game_mode.as_str() {
+            "Survival" => "0",
+            "Creative" => "1",
+            "Adventure" => "2",
+            _ => "",
+        };
+
+        [
+            self.edition.clone(),
+            self.motd.replace(';', ","),
+            self.protocol.to_string(),
+            self.version.clone(),
+            self.online_players.to_string(),
+            self.max_players.to_string(),
+            self.server_guid.to_string(),
+            self.sub_motd.replace(';', ","),
+            self.game_mode.clone(),
+            game_mode_id.to_string(),
+            port(self.port_v4),
+            port(self.port_v6),
+        ]
+        .join(";")
+            + ";"
+    }
+}
+
+/// Unconnected Ping を送るクライアント
+pub struct Bedrock {
+    socket: UdpSocket,
+    guid: i64,
+}
+
+impl Bedrock {
+    pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let socket = query::connect_socket(host, port).await?;
+
+        Ok(Self {
+            socket,
+            guid: random_guid(),
+        })
+    }
+
+    pub async fn status(&mut self) -> Result<BedrockStatus> {
+        let sent = now();
+        let mut packet = vec![UNCONNECTED_PING];
+        packet.extend(sent.to_be_bytes());
+        packet.extend(MAGIC);
+        packet.extend(self.guid.to_be_bytes());
+        self.socket.send(&packet).await?;
+
+        let mut buf = vec![0_u8; 2048];
+        loop {
+            let len = time::timeout(TIMEOUT, self.socket.recv(&mut buf)).await??;
+            // 前の要求への遅れた応答は捨てる
+            if let Some((time, server_id)) = parse_pong(&buf[..len]) {
+                if time == sent {
+                    return BedrockStatus::parse(&server_id);
+                }
+            }
+        }
+    }
+}
+
+/// 応答する側が受け取る要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Pong でそのまま返す時刻
+    Ping { time: i64 },
+    /// 接続の始まり。ここから先は RakNet のセッションになる。
+    OpenConnection,
+}
+
+impl Request {
+    /// 接続前のパケットでなければ `None`
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let (&id, rest) = packet.split_first()?;
+        match id {
+            UNCONNECTED_PING | UNCONNECTED_PING_OPEN_CONNECTIONS => {
+                let (time, rest) = rest.split_first_chunk::<8>()?;
+                rest.starts_with(&MAGIC).then(|| Request::Ping {
+                    time: i64::from_be_bytes(*time),
+                })
+            }
+            OPEN_CONNECTION_REQUEST_1 => {
+                rest.starts_with(&MAGIC).then_some(Request::OpenConnection)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn encode_pong(time: i64, status: &BedrockStatus) -> Vec<u8> {
+    let server_id = status.to_server_id();
+    let mut packet = vec![UNCONNECTED_PONG];
+    packet.extend(time.to_be_bytes());
+    packet.extend(status.server_guid.to_be_bytes());
+    packet.extend(MAGIC);
+    packet.extend((server_id.len() as u16).to_be_bytes());
+    packet.extend(server_id.as_bytes());
+    packet
+}
+
+fn parse_pong(packet: &[u8]) -> Option<(i64, String)> {
+    let rest = packet.strip_prefix(&[UNCONNECTED_PONG])?;
+    let (time, rest) = rest.split_first_chunk::<8>()?;
+    let (_server_guid, rest) = rest.split_first_chunk::<8>()?;
+    let rest = rest.strip_prefix(&MAGIC)?;
+    let (len, rest) = rest.split_first_chunk::<2>()?;
+    let server_id = rest.get(..u16::from_be_bytes(*len) as usize)?;
+
+    Some((
+        i64::from_be_bytes(*time),
+        String::from_utf8_lossy(server_id).into_owned(),
+    ))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// 応答をほかのサーバと見分けるためのもので、推測されても困らない
+pub fn random_guid() -> i64 {
+    uuid::Uuid::new_v4().as_u64_pair().0 as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> BedrockStatus {
+        BedrockStatus {
+            edition: "MCPE".to_string(),
+            motd: "Sleeping; join to wake".to_string(),
+            protocol: PROTOCOL,
+            version: VERSION.to_string(),
+            online_players: 0,
+            max_players: 10,
+            server_guid: 42,
+            sub_motd: "world".to_string(),
+            game_mode: "Survival".to_string(),
+            port_v4: Some(19132),
+            port_v6: None,
+        }
+    }
+
+    fn packet(id: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![id];
+        packet.extend(body);
+        packet
+    }
+
+    #[test]
+    fn parses_requests() {
+        let ping = [&1234_i64.to_be_bytes()[..], &MAGIC, &[0; 8]].concat();
+        assert_eq!(
+            Request::parse(&packet(UNCONNECTED_PING, &ping)),
+            Some(Request::Ping { time: 1234 })
+        );
+        assert_eq!(
+            Request::parse(&packet(UNCONNECTED_PING_OPEN_CONNECTIONS, &ping)),
+            Some(Request::Ping { time: 1234 })
+        );
+        assert_eq!(
+            Request::parse(&packet(
+                OPEN_CONNECTION_REQUEST_1,
+                &[&MAGIC[..], &[11]].concat()
+            )),
+            Some(Request::OpenConnection)
+        );
+
+        // magic が違う、短い、接続後のパケット
+        let mut bad_magic = ping.clone();
+        bad_magic[8] ^= 0xFF;
+        assert_eq!(Request::parse(&packet(UNCONNECTED_PING, &bad_magic)), None);
+        assert_eq!(Request::parse(&packet(UNCONNECTED_PING, &ping[..4])), None);
+        assert_eq!(
+            Request::parse(&packet(OPEN_CONNECTION_REQUEST_1, &[0; 16])),
+            None
+        );
+        assert_eq!(Request::parse(&packet(0x84, &ping)), None);
+        assert_eq!(Request::parse(&[]), None);
+    }
+
+    #[test]
+    fn round_trips_pong() {
+        let pong = encode_pong(1234, &status());
+        let (time, server_id) = parse_pong(&pong).unwrap();
+        assert_eq!(time, 1234);
+
+        let parsed = BedrockStatus::parse(&server_id).unwrap();
+        assert_eq!(parsed.server_guid, 42);
+        assert_eq!(parsed.max_players, 10);
+        assert_eq!(parsed.port_v4, Some(19132));
+        assert_eq!(parsed.port_v6, None);
+
+        assert_eq!(parse_pong(&pong[..pong.len() - 1]), None);
+        assert_eq!(parse_pong(&packet(UNCONNECTED_PING, &pong[1..])), None);
+    }
+
+    #[test]
+    fn escapes_separator_in_server_id() {
+        let server_id = status().to_server_id();
+        assert_eq!(
+            server_id,
+            "MCPE;Sleeping, join to wake;827;1.21.100;0;10;42;world;Survival;0;19132;;"
+        );
+        assert_eq!(
+            BedrockStatus::parse(&server_id).unwrap().motd,
+            "Sleeping, join to wake"
+        );
+    }
+
+    #[test]
+    fn parses_server_id_without_trailing_fields() {
+        let status = BedrockStatus::parse("MCPE;Old server;390;1.14.60;3;20").unwrap();
+        assert_eq!(status.motd, "Old server");
+        assert_eq!(status.protocol, 390);
+        assert_eq!(status.online_players, 3);
+        assert_eq!(status.max_players, 20);
+        assert_eq!(status.server_guid, 0);
+        assert_eq!(status.sub_motd, "");
+        assert_eq!(status.port_v4, None);
+
+        assert!(BedrockStatus::parse("MCPE;Too short;390;1.14.60;3").is_err());
+        assert!(BedrockStatus::parse("MCPE;Bad;390;1.14.60;three;20").is_err());
+    }
+}
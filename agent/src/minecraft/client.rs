@@ -1,15 +1,34 @@
+use std::{
+    io,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::time;
+
 use super::{
-    connection::Connection,
+    connection::{split_address, Connection},
+    dns::Resolver,
+    legacy,
     packet::{
-        handshake::Handshake, status_request::StatusRequest, status_response::StatusResponse,
+        handshake::Handshake,
+        ping::{Ping, Pong},
+        status_request::StatusRequest,
+        status_response::StatusResponse,
     },
+    version, Error, Result,
 };
 
+pub const DEFAULT_PORT: u16 = 25565;
+/// 接続を受け付けたまま応答しないサーバを待つ上限
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct Client {
     status: ClientStatus,
     conn: Connection,
     host: String,
     port: u16,
+    protocol: i32,
 }
 
 enum ClientStatus {
@@ -18,46 +37,151 @@ enum ClientStatus {
 }
 
 impl Client {
-    pub fn new(host: &str, port: u16) -> anyhow::Result<Self> {
-        let conn = Connection::new(host, port)?;
+    pub async fn new(host: &str, port: u16) -> Result<Self> {
+        let conn = Connection::new(host, port).await?;
 
         Ok(Client {
             status: ClientStatus::BeforeHandshake,
             conn,
             host: host.to_string(),
             port,
+            protocol: version::LATEST,
         })
     }
 
-    pub fn handshake(&mut self) -> anyhow::Result<()> {
+    /// `host[:port]` に接続する。port を省略したときは SRV レコード (`_minecraft._tcp`) を探す。
+    pub async fn connect(address: &str, resolver: &Resolver) -> Result<Self> {
+        let (host, port) = split_address(address).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid address: {}", address),
+            )
+        })?;
+
+        if let Some(port) = port {
+            return Self::new(host, port).await;
+        }
+
+        let srv = match host.parse::<IpAddr>() {
+            Ok(_) => None,
+            // 引けなければ SRV なしとして扱う
+            Err(_) => resolver.lookup_minecraft(host).await.ok().flatten(),
+        };
+
+        match srv {
+            Some((target, port)) => {
+                // ハンドシェイクにはプレイヤーが入力したアドレスを載せる
+                let conn = Connection::new(&target, port).await?;
+                Ok(Client {
+                    status: ClientStatus::BeforeHandshake,
+                    conn,
+                    host: host.to_string(),
+                    port: DEFAULT_PORT,
+                    protocol: version::LATEST,
+                })
+            }
+            None => Self::new(host, DEFAULT_PORT).await,
+        }
+    }
+
+    /// ハンドシェイクで名乗るプロトコル番号。サーバによってはこれで応答が変わる。
+    pub fn with_protocol(mut self, protocol: i32) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub async fn handshake(&mut self) -> Result<()> {
         let handshake = Handshake {
-            version: 765,
+            version: self.protocol,
             host: self.host.clone(),
             port: self.port,
             next_status: 1,
         };
-        self.conn.send_packet(handshake)?;
+        self.conn.send_packet(handshake).await?;
 
         self.status = ClientStatus::AfterHandshake;
 
         Ok(())
     }
 
-    pub fn status(&mut self) -> anyhow::Result<StatusResponse> {
+    pub async fn status(&mut self) -> Result<StatusResponse> {
+        if let ClientStatus::BeforeHandshake = self.status {
+            self.handshake().await?;
+        }
+
+        time::timeout(RESPONSE_TIMEOUT, async {
+            self.conn.send_packet(StatusRequest {}).await?;
+            self.conn.read_packet().await
+        })
+        .await?
+    }
+
+    /// Ping を送り、Pong が返ってくるまでの時間を測る
+    ///
+    /// サーバは Pong を返したあと接続を閉じるので、最後に呼ぶこと。
+    pub async fn ping(&mut self) -> Result<Duration> {
         if let ClientStatus::BeforeHandshake = self.status {
-            self.handshake()?;
+            self.handshake().await?;
         }
 
-        self.conn.send_packet(StatusRequest {})?;
-        let res = self.conn.read_handshake_resp_packet()?;
+        let payload = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+
+        let start = Instant::now();
+        let pong: Pong = time::timeout(RESPONSE_TIMEOUT, async {
+            self.conn.send_packet(Ping { payload }).await?;
+            self.conn.read_packet().await
+        })
+        .await??;
+        let latency = start.elapsed();
+
+        if pong.payload != payload {
+            return Err(Error::protocol(format!(
+                "pong payload mismatch: {} != {}",
+                pong.payload, payload
+            )));
+        }
 
-        let value: StatusResponse = serde_json::from_str(&res)?;
+        Ok(latency)
+    }
 
-        Ok(value)
+    /// 1.7 より前のサーバに legacy ping で状態を聞く。ハンドシェイク前にだけ使える。
+    pub async fn legacy_status(self) -> Result<StatusResponse> {
+        let (mut stream, _) = self.conn.into_parts();
+        time::timeout(
+            RESPONSE_TIMEOUT,
+            legacy::status(&mut stream, &self.host, self.port),
+        )
+        .await?
     }
 
-    pub fn get_online_players_count(&mut self) -> anyhow::Result<usize> {
-        let status = self.status()?;
+    pub async fn get_online_players_count(&mut self) -> Result<usize> {
+        let status = self.status().await?;
         Ok(status.players.online)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_server_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // 接続は受け付けるが何も返さない
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+        let mut client = Client::new("127.0.0.1", port).await.unwrap();
+        let _stream = accept.await.unwrap();
+
+        let e = client.status().await.unwrap_err();
+        assert!(matches!(e, Error::Timeout));
+        assert!(e.is_unreachable());
+        assert!(matches!(client.ping().await, Err(Error::Timeout)));
+    }
+}
@@ -0,0 +1,169 @@
+use bytes::{Buf, BufMut, BytesMut};
+use integer_encoding::{VarInt, VarIntWriter};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{
+    packet::{PacketEncoder, RawPacket},
+    Error, Result,
+};
+
+/// VarInt 3 バイトで表せる最大長
+pub const MAX_FRAME_SIZE: usize = (1 << 21) - 1;
+
+/// 長さ付きフレームとパケットの相互変換 (圧縮・暗号化なし)
+#[derive(Debug, Clone)]
+pub struct PacketCodec {
+    max_frame_size: usize,
+}
+
+impl PacketCodec {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self { max_frame_size }
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new(MAX_FRAME_SIZE)
+    }
+}
+
+/// 先頭の VarInt を読む。足りなければ `None`。
+fn peek_varint(buf: &[u8]) -> Result<Option<(u32, usize)>> {
+    for (i, byte) in buf.iter().enumerate().take(5) {
+        if byte & 0x80 == 0 {
+            let (value, len) = u32::decode_var(&buf[..=i]).ok_or(Error::InvalidVarInt)?;
+            return Ok(Some((value, len)));
+        }
+    }
+
+    if 5 <= buf.len() {
+        return Err(Error::InvalidVarInt);
+    }
+
+    Ok(None)
+}
+
+impl Decoder for PacketCodec {
+    type Item = RawPacket;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some((len, len_size)) = peek_varint(src)? else {
+            return Ok(None);
+        };
+
+        let len = len as usize;
+        if self.max_frame_size < len {
+            return Err(Error::FrameTooLarge(len));
+        }
+
+        if src.len() < len_size + len {
+            src.reserve(len_size + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(len_size);
+        let mut frame = src.split_to(len);
+
+        let (id, id_size) = peek_varint(&frame)?.ok_or(Error::InvalidVarInt)?;
+        frame.advance(id_size);
+
+        Ok(Some(RawPacket {
+            id,
+            payload: frame.to_vec(),
+        }))
+    }
+}
+
+impl<P: PacketEncoder> Encoder<P> for PacketCodec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: P, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut buf: Vec<u8> = vec![];
+        buf.write_varint(packet.packet_id())?;
+        packet.encode(&mut buf)?;
+
+        if self.max_frame_size < buf.len() {
+            return Err(Error::FrameTooLarge(buf.len()));
+        }
+
+        let mut writer = dst.writer();
+        writer.write_varint(buf.len() as u32)?;
+        dst.put_slice(&buf);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_partial_varint() {
+        let mut codec = PacketCodec::default();
+        // 長さの VarInt が続きのバイトを待っている
+        let mut buf = BytesMut::from(&[0x80][..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(&buf[..], &[0x80]);
+
+        // 長さ 128 の 2 バイト目が届いても、本体が足りない
+        buf.put_u8(0x01);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.put_u8(0x05);
+        buf.put_slice(&[0xAA; 127]);
+        let packet = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(packet.id, 0x05);
+        assert_eq!(packet.payload, vec![0xAA; 127]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn rejects_too_long_varint() {
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::from(&[0xFF; 5][..]);
+        assert!(matches!(codec.decode(&mut buf), Err(Error::InvalidVarInt)));
+    }
+
+    #[test]
+    fn rejects_oversized_frame() {
+        let mut codec = PacketCodec::new(16);
+        // 長さ 17
+        let mut buf = BytesMut::from(&[0x11, 0x00][..]);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::FrameTooLarge(17))
+        ));
+
+        let packet = RawPacket {
+            id: 0x00,
+            payload: vec![0; 16],
+        };
+        assert!(matches!(
+            codec.encode(packet, &mut BytesMut::new()),
+            Err(Error::FrameTooLarge(17))
+        ));
+    }
+
+    #[test]
+    fn decodes_multiple_frames_in_one_buffer() {
+        let mut codec = PacketCodec::default();
+        let mut buf = BytesMut::new();
+        for (id, payload) in [(0x00, vec![]), (0x01, vec![1, 2, 3]), (0x02, vec![4])] {
+            codec.encode(RawPacket { id, payload }, &mut buf).unwrap();
+        }
+        // 次のフレームの途中まで
+        buf.put_slice(&[0x03, 0x03]);
+
+        let ids: Vec<_> = std::iter::from_fn(|| codec.decode(&mut buf).unwrap())
+            .map(|packet| (packet.id, packet.payload))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![(0x00, vec![]), (0x01, vec![1, 2, 3]), (0x02, vec![4])]
+        );
+        assert_eq!(&buf[..], &[0x03, 0x03]);
+    }
+}
@@ -1,48 +1,155 @@
-use core::panic;
 use std::{
-    io::{Cursor, Read},
-    net::{SocketAddr, TcpStream},
+    io,
+    net::{IpAddr, SocketAddr},
     time::Duration,
 };
 
-use integer_encoding::VarIntReader;
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use tokio::{
+    net::{self, TcpStream},
+    time,
+};
+use tokio_util::codec::{Framed, FramedParts};
+
+use super::{
+    codec::PacketCodec,
+    packet::{PacketDecoder, PacketEncoder, RawPacket},
+    Error, Result,
+};
 
-use super::packet::{PacketEncoder, WritePacketExt};
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct Connection {
-    stream: TcpStream,
+    framed: Framed<TcpStream, PacketCodec>,
 }
 
 impl Connection {
-    pub fn new(host: &str, port: u16) -> anyhow::Result<Self> {
-        let addr: SocketAddr = format!("{}:{}", host, port).parse().unwrap();
-        let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))?;
-        stream.set_nodelay(true)?;
+    /// ホスト名なら解決したアドレスを順に試す。IPv6 は `[::1]` のように括弧付きでもよい。
+    pub async fn new(host: &str, port: u16) -> Result<Self> {
+        Ok(Self::from_stream(connect(host, port).await?))
+    }
 
-        Ok(Connection { stream })
+    pub fn from_stream(stream: TcpStream) -> Self {
+        Connection {
+            framed: Framed::new(stream, PacketCodec::default()),
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.framed.get_ref().peer_addr()?)
     }
 
-    pub fn send_packet<P: PacketEncoder>(&mut self, packet: P) -> anyhow::Result<()> {
-        self.stream.write_packet(packet)?;
-        Ok(())
+    pub async fn send_packet<P: PacketEncoder>(&mut self, packet: P) -> Result<()> {
+        self.framed.send(packet).await
     }
 
-    pub fn read_handshake_resp_packet(&mut self) -> anyhow::Result<String> {
-        let _: u32 = self.stream.read_varint()?;
-        let packet_id: u32 = self.stream.read_varint()?;
-        if packet_id != 0x00 {
-            panic!("Unsupported protocol: packet_id={}", packet_id);
+    pub async fn read_raw_packet(&mut self) -> Result<RawPacket> {
+        match self.framed.next().await {
+            Some(packet) => packet,
+            None => Err(Error::Disconnected("connection closed".to_string())),
         }
+    }
 
-        let len: u32 = self.stream.read_varint()?;
+    pub async fn read_packet<P: PacketDecoder>(&mut self) -> Result<P> {
+        self.read_raw_packet().await?.decode()
+    }
+
+    /// 生の TCP ストリームと、まだ解釈していない受信済みのバイト列に戻す
+    pub fn into_parts(self) -> (TcpStream, BytesMut) {
+        let FramedParts { io, read_buf, .. } = self.framed.into_parts();
+        (io, read_buf)
+    }
+
+    pub fn into_framed(self) -> Framed<TcpStream, PacketCodec> {
+        self.framed
+    }
+}
 
-        let mut buf = vec![0; len as usize];
-        self.stream.read_exact(&mut buf)?;
+/// ホスト名なら解決したアドレスを順に試して TCP 接続する
+pub(crate) async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
 
-        let mut cur = Cursor::new(buf);
-        let mut s = String::new();
-        cur.read_to_string(&mut s)?;
+    let addrs: Vec<SocketAddr> = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => time::timeout(CONNECT_TIMEOUT, net::lookup_host((host, port)))
+            .await??
+            .collect(),
+    };
 
-        Ok(s)
+    let mut last_error = Error::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} did not resolve to any address", host),
+    ));
+    for addr in addrs {
+        match time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Ok(Err(e)) => last_error = e.into(),
+            Err(e) => last_error = e.into(),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// `host`, `host:port`, `[v6]`, `[v6]:port`, 括弧なしの IPv6 を host と port に分ける
+pub fn split_address(address: &str) -> Option<(&str, Option<u16>)> {
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        return match rest {
+            "" => Some((host, None)),
+            _ => Some((host, Some(rest.strip_prefix(':')?.parse().ok()?))),
+        };
+    }
+
+    if address.parse::<IpAddr>().is_ok() {
+        return Some((address, None));
+    }
+
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && !host.contains(':') => {
+            Some((host, Some(port.parse().ok()?)))
+        }
+        Some(_) => None,
+        None if address.is_empty() => None,
+        None => Some((address, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Split<'a> = Option<(&'a str, Option<u16>)>;
+
+    #[test]
+    fn splits_addresses() {
+        let cases: &[(&str, Split)] = &[
+            ("example.com", Some(("example.com", None))),
+            ("example.com:25566", Some(("example.com", Some(25566)))),
+            ("127.0.0.1", Some(("127.0.0.1", None))),
+            ("127.0.0.1:25565", Some(("127.0.0.1", Some(25565)))),
+            ("::1", Some(("::1", None))),
+            ("2001:db8::1", Some(("2001:db8::1", None))),
+            ("[::1]", Some(("::1", None))),
+            ("[::1]:25565", Some(("::1", Some(25565)))),
+            ("", None),
+            (":25565", None),
+            ("example.com:port", None),
+            ("example.com:65536", None),
+            ("[::1", None),
+            ("[::1]25565", None),
+            ("a:b:c", None),
+        ];
+
+        for &(address, expected) in cases {
+            assert_eq!(split_address(address), expected, "{}", address);
+        }
     }
 }
@@ -0,0 +1,381 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{net::UdpSocket, time};
+
+use super::{Error, Result};
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// SRV レコードを引くための最小限の DNS クライアント (UDP のみ)
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    nameserver: SocketAddr,
+}
+
+impl Resolver {
+    pub fn new(nameserver: SocketAddr) -> Self {
+        Self { nameserver }
+    }
+
+    /// /etc/resolv.conf の最初の nameserver を使う
+    pub fn system() -> Result<Self> {
+        let conf = std::fs::read_to_string(RESOLV_CONF)?;
+        let nameserver = conf
+            .lines()
+            .filter_map(|line| line.strip_prefix("nameserver"))
+            .find_map(|addr| addr.trim().parse::<IpAddr>().ok())
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no nameserver in resolv.conf"))?;
+
+        Ok(Self::new(SocketAddr::new(nameserver, 53)))
+    }
+
+    /// `_minecraft._tcp.<host>` を引いて、接続すべき host と port を返す
+    pub async fn lookup_minecraft(&self, host: &str) -> Result<Option<(String, u16)>> {
+        let records = self
+            .lookup_srv(&format!("_minecraft._tcp.{}", host))
+            .await?;
+
+        // target が "." ならそのサービスは提供されていない
+        Ok(records
+            .into_iter()
+            .find(|record| !record.target.is_empty())
+            .map(|record| (record.target, record.port)))
+    }
+
+    /// 優先度の高い (priority が小さく weight が大きい) 順に返す。存在しなければ空。
+    pub async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>> {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u16)
+            .unwrap_or(0);
+        let query = build_query(id, name)?;
+
+        let bind: SocketAddr = match self.nameserver {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0_u16; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(self.nameserver).await?;
+        socket.send(&query).await?;
+
+        let mut buf = [0_u8; 1500];
+        let response = loop {
+            let len = time::timeout(QUERY_TIMEOUT, socket.recv(&mut buf)).await??;
+            // 別の問い合わせへの応答は無視する
+            if 2 <= len && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                break &buf[..len];
+            }
+        };
+
+        let mut records = parse_srv_response(response)?;
+        records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+
+        Ok(records)
+    }
+}
+
+fn build_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut buf = vec![];
+    buf.extend_from_slice(&id.to_be_bytes());
+    // 再帰問い合わせ
+    buf.extend_from_slice(&0x0100_u16.to_be_bytes());
+    // QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+    buf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || 63 < label.len() {
+            return Err(Error::protocol("DNS: invalid domain name"));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+
+    buf.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(buf)
+}
+
+fn read_u16(msg: &[u8], offset: usize) -> Result<u16> {
+    msg.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| Error::protocol("DNS: truncated message"))
+}
+
+/// 圧縮されている可能性のある名前を読む。名前の直後の位置も返す。
+fn read_name(msg: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+
+    // 圧縮ポインタのループ対策
+    for _ in 0..128 {
+        let len = *msg
+            .get(offset)
+            .ok_or_else(|| Error::protocol("DNS: truncated name"))? as usize;
+
+        if len == 0 {
+            return Ok((labels.join("."), end.unwrap_or(offset + 1)));
+        }
+
+        if len & 0xC0 == 0xC0 {
+            let pointer = read_u16(msg, offset)? & 0x3FFF;
+            end.get_or_insert(offset + 2);
+            offset = pointer as usize;
+            continue;
+        }
+
+        let label = msg
+            .get(offset + 1..offset + 1 + len)
+            .ok_or_else(|| Error::protocol("DNS: truncated label"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+
+    Err(Error::protocol("DNS: too many labels"))
+}
+
+fn parse_srv_response(msg: &[u8]) -> Result<Vec<SrvRecord>> {
+    if msg.len() < 12 {
+        return Err(Error::protocol("DNS: truncated header"));
+    }
+
+    let flags = read_u16(msg, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(Error::protocol("DNS: not a response"));
+    }
+    match (flags & 0x000F) as u8 {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(vec![]),
+        rcode => {
+            return Err(Error::protocol(format!(
+                "DNS: server returned rcode {}",
+                rcode
+            )))
+        }
+    }
+
+    let questions = read_u16(msg, 4)?;
+    let answers = read_u16(msg, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (_, next) = read_name(msg, offset)?;
+        offset = next + 4;
+    }
+
+    let mut records = vec![];
+    for _ in 0..answers {
+        let (_, next) = read_name(msg, offset)?;
+        let kind = read_u16(msg, next)?;
+        let rdlength = read_u16(msg, next + 8)? as usize;
+        let rdata = next + 10;
+        if msg.len() < rdata + rdlength {
+            return Err(Error::protocol("DNS: truncated record"));
+        }
+
+        // CNAME などは読み飛ばす
+        if kind == TYPE_SRV {
+            let (target, _) = read_name(msg, rdata + 6)?;
+            records.push(SrvRecord {
+                priority: read_u16(msg, rdata)?,
+                weight: read_u16(msg, rdata + 2)?,
+                port: read_u16(msg, rdata + 4)?,
+                target,
+            });
+        }
+
+        offset = rdata + rdlength;
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "_minecraft._tcp.example.com";
+
+    fn encode_name(name: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        for label in name.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0);
+        buf
+    }
+
+    /// 問い合わせ名へのポインタを名前にした SRV レコード
+    fn srv_answer(priority: u16, weight: u16, port: u16, target: &[u8]) -> Vec<u8> {
+        let mut rdata = vec![];
+        rdata.extend_from_slice(&priority.to_be_bytes());
+        rdata.extend_from_slice(&weight.to_be_bytes());
+        rdata.extend_from_slice(&port.to_be_bytes());
+        rdata.extend_from_slice(target);
+
+        let mut buf = vec![0xC0, 12];
+        buf.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&300_u32.to_be_bytes());
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&rdata);
+        buf
+    }
+
+    /// `query` への応答。answers は個数と中身。
+    fn response(query: &[u8], rcode: u8, answers: &[Vec<u8>]) -> Vec<u8> {
+        let mut buf = query[..2].to_vec();
+        buf.extend_from_slice(&(0x8180 | rcode as u16).to_be_bytes());
+        buf.extend_from_slice(&1_u16.to_be_bytes());
+        buf.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);
+        buf.extend_from_slice(&query[12..]);
+        for answer in answers {
+            buf.extend_from_slice(answer);
+        }
+        buf
+    }
+
+    /// 一度だけ応答する DNS サーバ
+    async fn stub(respond: impl FnOnce(&[u8]) -> Vec<u8> + Send + 'static) -> Resolver {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0_u8; 512];
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            socket.send_to(&respond(&buf[..len]), from).await.unwrap();
+        });
+
+        Resolver::new(addr)
+    }
+
+    #[test]
+    fn builds_query() {
+        let query = build_query(0x1234, NAME).unwrap();
+        assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&query[12..query.len() - 4], &encode_name(NAME)[..]);
+        assert_eq!(&query[query.len() - 4..], &[0, 33, 0, 1]);
+
+        assert!(build_query(0, "a..b").is_err());
+    }
+
+    #[test]
+    fn parses_srv_records() {
+        let query = build_query(1, NAME).unwrap();
+        // CNAME は読み飛ばし、target の圧縮ポインタは問い合わせ名の途中を指す
+        let cname = {
+            let mut buf = vec![0xC0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2];
+            buf.extend_from_slice(&[0xC0, 12]);
+            buf
+        };
+        let msg = response(
+            &query,
+            0,
+            &[
+                cname,
+                srv_answer(10, 5, 25566, &encode_name("mc.example.net")),
+                srv_answer(0, 0, 25565, &[0xC0, 28]),
+            ],
+        );
+
+        assert_eq!(
+            parse_srv_response(&msg).unwrap(),
+            vec![
+                SrvRecord {
+                    priority: 10,
+                    weight: 5,
+                    port: 25566,
+                    target: "mc.example.net".to_string(),
+                },
+                SrvRecord {
+                    priority: 0,
+                    weight: 0,
+                    port: 25565,
+                    target: "example.com".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        let query = build_query(1, NAME).unwrap();
+
+        // 問い合わせのまま (QR ビットが立っていない)
+        assert!(parse_srv_response(&query).is_err());
+        assert!(parse_srv_response(&query[..8]).is_err());
+        // SERVFAIL
+        assert!(parse_srv_response(&response(&query, 2, &[])).is_err());
+
+        let mut msg = response(&query, 0, &[srv_answer(0, 0, 25565, &[0])]);
+        msg.truncate(msg.len() - 3);
+        assert!(parse_srv_response(&msg).is_err());
+
+        // 自分自身を指す圧縮ポインタ (応答は問い合わせと同じ長さの後に answer が続く)
+        let target = (query.len() + 12 + 6) as u8;
+        let msg = response(&query, 0, &[srv_answer(0, 0, 25565, &[0xC0, target])]);
+        assert!(parse_srv_response(&msg).is_err());
+    }
+
+    #[tokio::test]
+    async fn looks_up_srv_from_stub() {
+        let resolver = stub(|query| {
+            response(
+                query,
+                0,
+                &[
+                    srv_answer(20, 0, 25567, &encode_name("backup.example.com")),
+                    // "." はサービスが無いことを表す
+                    srv_answer(0, 0, 0, &[0]),
+                    srv_answer(10, 0, 25566, &encode_name("mc.example.com")),
+                ],
+            )
+        })
+        .await;
+
+        assert_eq!(
+            resolver.lookup_minecraft("example.com").await.unwrap(),
+            Some(("mc.example.com".to_string(), 25566))
+        );
+    }
+
+    #[tokio::test]
+    async fn nxdomain_is_no_record() {
+        let resolver = stub(|query| response(query, RCODE_NXDOMAIN, &[])).await;
+        assert_eq!(
+            resolver.lookup_minecraft("example.com").await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn malformed_answer_is_error() {
+        let resolver = stub(|query| {
+            let mut msg = response(query, 0, &[srv_answer(0, 0, 25565, &[0])]);
+            msg.truncate(msg.len() - 4);
+            msg
+        })
+        .await;
+
+        assert!(resolver.lookup_minecraft("example.com").await.is_err());
+    }
+}
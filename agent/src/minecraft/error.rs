@@ -0,0 +1,130 @@
+use std::{fmt, io, string::FromUtf8Error};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// 接続や応答が時間内に終わらなかった
+    Timeout,
+    /// 別のパケットが届いた。相手のプロトコルが違う可能性が高い。
+    UnexpectedPacket {
+        expected: u32,
+        got: u32,
+    },
+    FrameTooLarge(usize),
+    InvalidVarInt,
+    /// 応答が壊れている。サーバは応答したが、話が通じなかった。
+    Protocol(String),
+    InvalidUtf8(FromUtf8Error),
+    Json(serde_json::Error),
+    /// 相手が接続を閉じた (理由が分かればその内容)
+    Disconnected(String),
+    /// RCON のパスワードが違う
+    AuthenticationFailed,
+}
+
+impl Error {
+    pub(crate) fn protocol(message: impl Into<String>) -> Self {
+        Error::Protocol(message.into())
+    }
+
+    /// サーバが落ちている (繋がらない・応答しない) ときのエラーか
+    ///
+    /// `false` ならサーバは応答したが、話が通じなかった。
+    pub fn is_unreachable(&self) -> bool {
+        match self {
+            // InvalidInput はこちらが渡したアドレスやコマンドの誤り
+            Error::Io(e) => !matches!(
+                e.kind(),
+                io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData
+            ),
+            Error::Timeout | Error::Disconnected(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Timeout => write!(f, "timed out"),
+            Error::UnexpectedPacket { expected, got } => write!(
+                f,
+                "unexpected packet: expected {:#04x}, got {:#04x}",
+                expected, got
+            ),
+            Error::FrameTooLarge(len) => write!(f, "frame too large: {} bytes", len),
+            Error::InvalidVarInt => write!(f, "invalid VarInt"),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+            Error::InvalidUtf8(e) => write!(f, "invalid UTF-8 string: {}", e),
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::Disconnected(reason) => write!(f, "disconnected: {}", reason),
+            Error::AuthenticationFailed => write!(f, "authentication failed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::InvalidUtf8(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Self {
+        Error::InvalidUtf8(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        Error::Timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_unreachable_errors() {
+        let io = |kind| Error::Io(io::Error::new(kind, "test"));
+
+        assert!(io(io::ErrorKind::ConnectionRefused).is_unreachable());
+        assert!(io(io::ErrorKind::ConnectionReset).is_unreachable());
+        assert!(Error::Timeout.is_unreachable());
+        assert!(Error::Disconnected("closed".to_string()).is_unreachable());
+
+        // 応答はあったが中身が壊れている、またはこちらの入力の誤り
+        assert!(!Error::protocol("pong payload mismatch").is_unreachable());
+        assert!(!Error::UnexpectedPacket {
+            expected: 0x00,
+            got: 0x01
+        }
+        .is_unreachable());
+        assert!(!Error::InvalidVarInt.is_unreachable());
+        assert!(!Error::FrameTooLarge(1 << 21).is_unreachable());
+        assert!(!Error::AuthenticationFailed.is_unreachable());
+        assert!(!io(io::ErrorKind::InvalidInput).is_unreachable());
+        assert!(!io(io::ErrorKind::InvalidData).is_unreachable());
+    }
+}
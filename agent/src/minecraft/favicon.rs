@@ -0,0 +1,219 @@
+use std::{fmt, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Error, Result};
+
+const DATA_URI_PREFIX: &str = "data:image/png;base64,";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// クライアントが表示できるサイズ
+pub const SIZE: u32 = 64;
+
+/// サーバ一覧に表示されるアイコン (PNG)
+#[derive(Clone, PartialEq, Eq)]
+pub struct Favicon {
+    png: Vec<u8>,
+}
+
+impl Favicon {
+    pub fn from_png(png: Vec<u8>) -> Result<Self> {
+        if !png.starts_with(PNG_SIGNATURE) {
+            return Err(Error::protocol("favicon is not a PNG image"));
+        }
+
+        let favicon = Self { png };
+        favicon
+            .dimensions()
+            .ok_or_else(|| Error::protocol("favicon has no IHDR chunk"))?;
+
+        Ok(favicon)
+    }
+
+    /// `data:image/png;base64,...`。古いサーバが入れてくる改行は無視する。
+    pub fn from_data_uri(uri: &str) -> Result<Self> {
+        let data = uri.strip_prefix(DATA_URI_PREFIX).ok_or_else(|| {
+            Error::protocol(format!("favicon must start with `{}`", DATA_URI_PREFIX))
+        })?;
+        let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        let png = STANDARD
+            .decode(data)
+            .map_err(|e| Error::protocol(format!("favicon is not valid base64: {}", e)))?;
+
+        Self::from_png(png)
+    }
+
+    /// サーバとして送るためのアイコンを読み込む。64x64 でなければエラー。
+    pub fn load(path: &Path) -> Result<Self> {
+        let favicon = Self::from_png(std::fs::read(path)?)?;
+
+        match favicon.dimensions() {
+            Some((SIZE, SIZE)) => Ok(favicon),
+            Some((width, height)) => Err(Error::protocol(format!(
+                "favicon must be {}x{}, got {}x{}",
+                SIZE, SIZE, width, height
+            ))),
+            None => unreachable!("checked in from_png"),
+        }
+    }
+
+    pub fn png(&self) -> &[u8] {
+        &self.png
+    }
+
+    /// IHDR から読んだ幅と高さ
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        // シグネチャ (8) + 長さ (4) + "IHDR" (4) のあとに幅と高さが続く
+        let ihdr = self.png.get(8..24)?;
+        if &ihdr[4..8] != b"IHDR" {
+            return None;
+        }
+
+        let width = u32::from_be_bytes(ihdr[8..12].try_into().ok()?);
+        let height = u32::from_be_bytes(ihdr[12..16].try_into().ok()?);
+        Some((width, height))
+    }
+
+    pub fn to_data_uri(&self) -> String {
+        format!("{}{}", DATA_URI_PREFIX, STANDARD.encode(&self.png))
+    }
+}
+
+impl fmt::Debug for Favicon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Favicon");
+        if let Some((width, height)) = self.dimensions() {
+            debug.field("width", &width).field("height", &height);
+        }
+        debug.field("bytes", &self.png.len()).finish()
+    }
+}
+
+impl Serialize for Favicon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_data_uri())
+    }
+}
+
+impl<'de> Deserialize<'de> for Favicon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let uri = String::deserialize(deserializer)?;
+        Self::from_data_uri(&uri).map_err(serde::de::Error::custom)
+    }
+}
+
+/// 壊れたアイコンのせいで応答全体を読めなくならないよう、読めなければ `None` にする
+pub(crate) fn deserialize_lenient<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Favicon>, D::Error> {
+    let uri = Option::<String>::deserialize(deserializer)?;
+    Ok(uri.and_then(|uri| Favicon::from_data_uri(&uri).ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minecraft::packet::status_response::StatusResponse;
+
+    /// IHDR までの PNG。中身はデコードしないのでそれ以降は無くてよい。
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(13_u32.to_be_bytes());
+        png.extend(b"IHDR");
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        png
+    }
+
+    #[test]
+    fn reads_dimensions_from_ihdr() {
+        let favicon = Favicon::from_png(png(64, 32)).unwrap();
+        assert_eq!(favicon.dimensions(), Some((64, 32)));
+    }
+
+    #[test]
+    fn rejects_non_png() {
+        assert!(Favicon::from_png(b"GIF89a".to_vec()).is_err());
+
+        let mut no_ihdr = png(64, 64);
+        no_ihdr[12..16].copy_from_slice(b"IDAT");
+        assert!(Favicon::from_png(no_ihdr).is_err());
+        assert!(Favicon::from_png(PNG_SIGNATURE.to_vec()).is_err());
+    }
+
+    #[test]
+    fn data_uri_round_trip() {
+        let favicon = Favicon::from_png(png(64, 64)).unwrap();
+        let uri = favicon.to_data_uri();
+        assert!(uri.starts_with(DATA_URI_PREFIX));
+        assert_eq!(Favicon::from_data_uri(&uri).unwrap(), favicon);
+
+        // 古いサーバは途中に改行を入れてくる
+        let (prefix, data) = uri.split_at(DATA_URI_PREFIX.len());
+        let wrapped = data
+            .as_bytes()
+            .chunks(16)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        let uri = format!("{}{} \n", prefix, wrapped);
+        assert_eq!(Favicon::from_data_uri(&uri).unwrap(), favicon);
+    }
+
+    #[test]
+    fn rejects_other_data_uris() {
+        let data = STANDARD.encode(png(64, 64));
+        assert!(Favicon::from_data_uri(&format!("data:image/jpeg;base64,{}", data)).is_err());
+        assert!(Favicon::from_data_uri(&data).is_err());
+        assert!(Favicon::from_data_uri(&format!("{}not base64!", DATA_URI_PREFIX)).is_err());
+
+        let gif = STANDARD.encode(b"GIF89a");
+        assert!(Favicon::from_data_uri(&format!("{}{}", DATA_URI_PREFIX, gif)).is_err());
+    }
+
+    #[test]
+    fn load_requires_64x64() {
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("favicon-test-{}-{}", std::process::id(), name));
+
+        let ok = path("64.png");
+        std::fs::write(&ok, png(64, 64)).unwrap();
+        let large = path("128.png");
+        std::fs::write(&large, png(128, 128)).unwrap();
+
+        let loaded = Favicon::load(&ok);
+        let rejected = Favicon::load(&large);
+        std::fs::remove_file(&ok).unwrap();
+        std::fs::remove_file(&large).unwrap();
+
+        assert_eq!(loaded.unwrap().dimensions(), Some((SIZE, SIZE)));
+        let message = rejected.unwrap_err().to_string();
+        assert!(message.contains("128x128"), "{}", message);
+    }
+
+    #[test]
+    fn broken_favicon_in_status_is_ignored() {
+        let status = |favicon: &str| {
+            serde_json::from_str::<StatusResponse>(&format!(
+                r#"{{
+                    "version": {{ "name": "1.21.1", "protocol": 767 }},
+                    "players": {{ "max": 20, "online": 0 }},
+                    "description": "A Minecraft Server",
+                    "favicon": {}
+                }}"#,
+                favicon
+            ))
+            .unwrap()
+        };
+
+        let uri = Favicon::from_png(png(64, 64)).unwrap().to_data_uri();
+        assert!(status(&format!("\"{}\"", uri)).favicon.is_some());
+        assert!(status("\"data:image/png;base64,broken\"").favicon.is_none());
+        assert!(status("\"data:image/gif;base64,R0lGODlh\"")
+            .favicon
+            .is_none());
+        assert!(status("null").favicon.is_none());
+    }
+}
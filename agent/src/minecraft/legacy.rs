@@ -0,0 +1,345 @@
+//! 1.7 より前のサーバリスト ping (0xFE)
+//!
+//! https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping#1.6
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+
+use super::{
+    packet::status_response::{Players, StatusResponse, Version},
+    raw_json_text::RawJsonText,
+    Error, Result,
+};
+
+const PING: u8 = 0xFE;
+const PLUGIN_MESSAGE: u8 = 0xFA;
+const KICK: u8 = 0xFF;
+const PING_HOST: &str = "MC|PingHost";
+
+/// 1.6 のクライアントが Ping に載せてくるプロトコル番号。
+/// プロトコルを送ってこない 1.6 より前のクライアントへの応答にも使う。
+pub const LEGACY_PROTOCOL: i32 = 78;
+
+/// 古いクライアントは続きを送らないことがあるので、少しだけ待つ
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+const PEEK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 受け取った legacy ping の種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyPing {
+    /// Beta 1.8 - 1.3: `FE` のみ
+    Beta,
+    /// 1.4 - 1.5: `FE 01`
+    V1_4,
+    /// 1.6: `FE 01 FA` と MC|PingHost
+    V1_6 {
+        protocol: u8,
+        host: String,
+        port: u16,
+    },
+}
+
+impl LegacyPing {
+    /// 1.6 より前はクライアントのプロトコルが分からない
+    pub fn protocol(&self) -> Option<i32> {
+        match self {
+            LegacyPing::V1_6 { protocol, .. } => Some(*protocol as i32),
+            _ => None,
+        }
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        match self {
+            LegacyPing::V1_6 { host, .. } => Some(host),
+            _ => None,
+        }
+    }
+}
+
+/// 接続の先頭が legacy ping か
+///
+/// 新しいハンドシェイクは長さの VarInt から始まるので、254 バイト以上のハンドシェイク
+/// (BungeeCord の IP 転送で host が長いときなど) も 0xFE から始まる。バニラと同じく
+/// 2 バイト目が 0x01 か、続きが来ないときだけ legacy ping とみなす。
+/// 長さがちょうど 254 なら `FE 01` の後にパケット ID 0x00 が続くので、3 バイト目も見る。
+pub async fn is_legacy_ping(stream: &TcpStream) -> Result<bool> {
+    let deadline = time::Instant::now() + READ_TIMEOUT;
+    let mut buf = [0_u8; 3];
+
+    loop {
+        let len = stream.peek(&mut buf).await?;
+        match buf[..len] {
+            [] => return Ok(false),
+            [first, ..] if first != PING => return Ok(false),
+            [_, second, ..] if second != 0x01 => return Ok(false),
+            [_, _, third] => return Ok(third == PLUGIN_MESSAGE),
+            // Beta と 1.4 は続きを送らずに応答を待つ
+            _ if deadline <= time::Instant::now() => return Ok(true),
+            _ => time::sleep(PEEK_INTERVAL).await,
+        }
+    }
+}
+
+pub async fn read_ping(stream: &mut TcpStream) -> Result<LegacyPing> {
+    let mut buf = vec![];
+
+    loop {
+        if let Some(ping) = parse_ping(&buf)? {
+            return Ok(ping);
+        }
+
+        let mut chunk = [0_u8; 256];
+        match time::timeout(READ_TIMEOUT, stream.read(&mut chunk)).await {
+            Ok(Ok(0)) | Err(_) => break,
+            Ok(Ok(len)) => buf.extend_from_slice(&chunk[..len]),
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+
+    // 続きが来なかったので、そこまでで判断する
+    match buf.as_slice() {
+        [PING] => Ok(LegacyPing::Beta),
+        [PING, 0x01, ..] => Ok(LegacyPing::V1_4),
+        [] => Err(Error::Disconnected("connection closed".to_string())),
+        [got, ..] => Err(Error::UnexpectedPacket {
+            expected: PING as u32,
+            got: *got as u32,
+        }),
+    }
+}
+
+/// 1.6 の ping が最後まで読めたら返す
+fn parse_ping(buf: &[u8]) -> Result<Option<LegacyPing>> {
+    let [PING, 0x01, PLUGIN_MESSAGE, rest @ ..] = buf else {
+        return Ok(None);
+    };
+
+    let mut reader = Reader(rest);
+    let Some(channel) = reader.string() else {
+        return Ok(None);
+    };
+    if channel != PING_HOST {
+        return Err(Error::protocol(format!(
+            "unexpected plugin channel: {}",
+            channel
+        )));
+    }
+
+    let (Some(_len), Some(protocol), Some(host), Some(port)) =
+        (reader.u16(), reader.u8(), reader.string(), reader.u32())
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(LegacyPing::V1_6 {
+        protocol,
+        host,
+        port: port as u16,
+    }))
+}
+
+/// ping の種類に合わせた形式で、切断メッセージとして状態を返す
+pub async fn write_status(
+    stream: &mut TcpStream,
+    ping: &LegacyPing,
+    status: &StatusResponse,
+) -> Result<()> {
+    let response = match ping {
+        // この形式では § が区切り文字になるので書式は使えない
+        LegacyPing::Beta => format!(
+            "{}§{}§{}",
+            status.description.to_plain().replace('§', ""),
+            status.players.online,
+            status.players.max
+        ),
+        _ => format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            status.version.protocol,
+            status.version.name,
+            status.description.to_legacy(),
+            status.players.online,
+            status.players.max
+        ),
+    };
+
+    let mut buf = vec![KICK];
+    write_utf16(&mut buf, &response);
+    stream.write_all(&buf).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// 1.6 形式の ping を送って状態を聞く
+pub async fn status(stream: &mut TcpStream, host: &str, port: u16) -> Result<StatusResponse> {
+    let mut data = vec![LEGACY_PROTOCOL as u8];
+    write_utf16(&mut data, host);
+    data.extend_from_slice(&(port as u32).to_be_bytes());
+
+    let mut buf = vec![PING, 0x01, PLUGIN_MESSAGE];
+    write_utf16(&mut buf, PING_HOST);
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(&data);
+    stream.write_all(&buf).await?;
+
+    let id = stream.read_u8().await?;
+    if id != KICK {
+        return Err(Error::UnexpectedPacket {
+            expected: KICK as u32,
+            got: id as u32,
+        });
+    }
+
+    let len = stream.read_u16().await? as usize;
+    let mut units = Vec::with_capacity(len);
+    for _ in 0..len {
+        units.push(stream.read_u16().await?);
+    }
+    let response = String::from_utf16(&units).map_err(|e| Error::protocol(e.to_string()))?;
+
+    parse_status(&response)
+}
+
+fn parse_status(response: &str) -> Result<StatusResponse> {
+    let number = |s: &str| {
+        s.parse::<usize>()
+            .map_err(|_| Error::protocol(format!("invalid number: {}", s)))
+    };
+
+    if let Some(rest) = response.strip_prefix("§1\0") {
+        let fields: Vec<&str> = rest.split('\0').collect();
+        let [protocol, name, motd, online, max] = fields[..] else {
+            return Err(Error::protocol(format!(
+                "unexpected response: {:?}",
+                response
+            )));
+        };
+
+        return Ok(StatusResponse {
+            version: Version {
+                name: name.to_string(),
+                protocol: protocol
+                    .parse()
+                    .map_err(|_| Error::protocol(format!("invalid protocol: {}", protocol)))?,
+            },
+            players: Players {
+                max: number(max)?,
+                online: number(online)?,
+                sample: None,
+            },
+            description: RawJsonText::from_legacy(motd),
+            favicon: None,
+            modinfo: None,
+        });
+    }
+
+    // Beta 1.8 - 1.3 の形式
+    let mut fields = response.rsplitn(3, '§');
+    let (Some(max), Some(online), Some(motd)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(Error::protocol(format!(
+            "unexpected response: {:?}",
+            response
+        )));
+    };
+
+    Ok(StatusResponse {
+        version: Version {
+            name: "Beta 1.8 - 1.3".to_string(),
+            protocol: -1,
+        },
+        players: Players {
+            max: number(max)?,
+            online: number(online)?,
+            sample: None,
+        },
+        description: RawJsonText::String(motd.to_string()),
+        favicon: None,
+        modinfo: None,
+    })
+}
+
+/// 長さ (UTF-16 の code unit 数) 付きの UTF-16BE 文字列
+fn write_utf16(buf: &mut Vec<u8>, s: &str) {
+    let units: Vec<u16> = s.encode_utf16().collect();
+    buf.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        buf.extend_from_slice(&unit.to_be_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u16()? as usize;
+        let units: Vec<u16> = self
+            .take(len * 2)?
+            .chunks(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        Some(String::from_utf16_lossy(&units))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// `sent` を送ってきた接続を受けた側から見る
+    async fn detect(sent: &[u8]) -> bool {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        client.write_all(sent).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        is_legacy_ping(&stream).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn detects_legacy_pings() {
+        assert!(detect(&[PING]).await);
+        assert!(detect(&[PING, 0x01]).await);
+        assert!(detect(&[PING, 0x01, PLUGIN_MESSAGE, 0x00, 0x0B]).await);
+    }
+
+    #[tokio::test]
+    async fn long_handshake_is_not_legacy() {
+        // 長さ 254 (FE 01) のハンドシェイクはパケット ID 0x00 が続く
+        assert!(!detect(&[PING, 0x01, 0x00, 0xFF, 0x05]).await);
+        // 長さ 382 (FE 02)
+        assert!(!detect(&[PING, 0x02, 0x00]).await);
+        assert!(!detect(&[0x10, 0x00]).await);
+    }
+}
@@ -0,0 +1,123 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use serde_json::Value;
+use std::io::Write;
+
+use super::{raw_json_text::RawJsonText, Result};
+
+// https://wiki.vg/NBT
+// 1.20.3 以降、Play 中のテキストコンポーネントは JSON ではなく NBT で送る
+
+const TAG_END: u8 = 0x00;
+const TAG_BYTE: u8 = 0x01;
+const TAG_INT: u8 = 0x03;
+const TAG_LONG: u8 = 0x04;
+const TAG_DOUBLE: u8 = 0x06;
+const TAG_STRING: u8 = 0x08;
+const TAG_LIST: u8 = 0x09;
+const TAG_COMPOUND: u8 = 0x0A;
+
+pub fn write_text_component<W: Write>(stream: &mut W, text: &RawJsonText) -> Result<()> {
+    let value = serde_json::to_value(text)?;
+    write_network_nbt(stream, &value)
+}
+
+/// ルートに名前を付けない (Network NBT) 形式で書き込む
+pub fn write_network_nbt<W: Write>(stream: &mut W, value: &Value) -> Result<()> {
+    stream.write_u8(tag_type(value))?;
+    write_payload(stream, value)
+}
+
+fn tag_type(value: &Value) -> u8 {
+    match value {
+        Value::Bool(_) => TAG_BYTE,
+        Value::Number(n) if n.is_f64() => TAG_DOUBLE,
+        Value::Number(n) if n.as_i64().is_some_and(|n| i32::try_from(n).is_ok()) => TAG_INT,
+        Value::Number(_) => TAG_LONG,
+        Value::String(_) => TAG_STRING,
+        Value::Array(_) => TAG_LIST,
+        Value::Object(_) | Value::Null => TAG_COMPOUND,
+    }
+}
+
+fn write_payload<W: Write>(stream: &mut W, value: &Value) -> Result<()> {
+    match value {
+        Value::Bool(b) => stream.write_u8(*b as u8)?,
+        Value::Number(n) => match tag_type(value) {
+            TAG_DOUBLE => stream.write_f64::<BigEndian>(n.as_f64().unwrap_or_default())?,
+            TAG_INT => stream.write_i32::<BigEndian>(n.as_i64().unwrap_or_default() as i32)?,
+            _ => stream.write_i64::<BigEndian>(n.as_i64().unwrap_or_default())?,
+        },
+        Value::String(s) => write_string(stream, s)?,
+        Value::Array(values) => {
+            // リストの要素は同じ型でなければならないので、
+            // 文字列とオブジェクトが混ざっていたら文字列を {"text": ...} で包む
+            let homogeneous = values
+                .windows(2)
+                .all(|w| tag_type(&w[0]) == tag_type(&w[1]));
+
+            if homogeneous {
+                stream.write_u8(values.first().map(tag_type).unwrap_or(TAG_END))?;
+                stream.write_i32::<BigEndian>(values.len() as i32)?;
+                for value in values {
+                    write_payload(stream, value)?;
+                }
+            } else {
+                stream.write_u8(TAG_COMPOUND)?;
+                stream.write_i32::<BigEndian>(values.len() as i32)?;
+                for value in values {
+                    match value {
+                        Value::Object(_) => write_payload(stream, value)?,
+                        _ => {
+                            let text = match value {
+                                Value::String(s) => s.clone(),
+                                _ => value.to_string(),
+                            };
+                            let mut map = serde_json::Map::new();
+                            map.insert("text".to_string(), Value::String(text));
+                            write_payload(stream, &Value::Object(map))?;
+                        }
+                    }
+                }
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                if value.is_null() {
+                    continue;
+                }
+
+                stream.write_u8(tag_type(value))?;
+                write_string(stream, key)?;
+                write_payload(stream, value)?;
+            }
+            stream.write_u8(TAG_END)?;
+        }
+        Value::Null => stream.write_u8(TAG_END)?,
+    }
+
+    Ok(())
+}
+
+/// Modified UTF-8 (NUL と BMP 外の文字の扱いが UTF-8 と異なる)
+fn write_string<W: Write>(stream: &mut W, s: &str) -> Result<()> {
+    let mut buf = Vec::with_capacity(s.len());
+    for unit in s.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => buf.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                buf.push(0xC0 | (unit >> 6) as u8);
+                buf.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                buf.push(0xE0 | (unit >> 12) as u8);
+                buf.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                buf.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    stream.write_u16::<BigEndian>(buf.len() as u16)?;
+    stream.write_all(&buf)?;
+
+    Ok(())
+}
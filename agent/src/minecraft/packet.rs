@@ -1,55 +1,116 @@
-use std::{
-    io::{Read, Write},
-    net::TcpStream,
-};
+use std::io::{Read, Write};
 
 use integer_encoding::{VarIntReader, VarIntWriter};
 
+use super::{Error, Result};
+
+pub mod configuration;
 pub mod disconnect_login;
 pub mod handshake;
+pub mod login_plugin;
+pub mod login_start;
+pub mod login_success;
 pub mod ping;
+pub mod play;
+pub mod set_compression;
 pub mod status_request;
 pub mod status_response;
 
-pub trait WritePacketExt {
-    fn write_packet<P: PacketEncoder>(&mut self, packet: P) -> anyhow::Result<()>;
+/// 中身を解釈していないパケット
+#[derive(Debug, Clone)]
+pub struct RawPacket {
+    pub id: u32,
+    pub payload: Vec<u8>,
 }
 
-impl WritePacketExt for TcpStream {
-    fn write_packet<P: PacketEncoder>(&mut self, packet: P) -> anyhow::Result<()> {
-        let mut buf: Vec<u8> = vec![];
+impl RawPacket {
+    pub fn decode<P: PacketDecoder>(&self) -> Result<P> {
+        let packet = *P::decode(&mut self.payload.as_slice()).map_err(|e| self.malformed(e))?;
+        if packet.packet_id() != self.id {
+            return Err(Error::UnexpectedPacket {
+                expected: packet.packet_id(),
+                got: self.id,
+            });
+        }
 
-        buf.write_varint(packet.packet_id())?;
-        packet.encode(&mut buf)?;
+        Ok(packet)
+    }
 
-        let mut packet_buf = vec![];
-        packet_buf.write_varint(buf.len() as u32)?;
-        packet_buf.write_all(&buf)?;
+    /// バージョンによって形式が変わるパケットを、送ってきた側のプロトコルで読む
+    pub fn decode_versioned<P: VersionedPacket>(&self, protocol: i32) -> Result<P> {
+        let packet = P::decode_versioned(&mut self.payload.as_slice(), protocol)
+            .map_err(|e| self.malformed(e))?;
+        if packet.packet_id() != self.id {
+            return Err(Error::UnexpectedPacket {
+                expected: packet.packet_id(),
+                got: self.id,
+            });
+        }
 
-        self.write_all(&packet_buf)?;
+        Ok(packet)
+    }
 
-        Ok(())
+    /// 受信済みのバイト列が途中で尽きたのは、接続ではなくパケットの中身の問題
+    fn malformed(&self, e: Error) -> Error {
+        match e {
+            Error::Io(e) => Error::protocol(format!("malformed packet {:#04x}: {}", self.id, e)),
+            e => e,
+        }
     }
 }
 
-pub fn read_packet<P: PacketDecoder>(stream: &mut TcpStream) -> anyhow::Result<P> {
-    let _packet_len: u32 = stream.read_varint()?;
-    let packet_id: u32 = stream.read_varint()?;
-
-    let packet = *P::decode(stream)?;
-    if packet.packet_id() != packet_id {
-        return Err(anyhow::anyhow!("Invalid packet_id"));
+impl PacketEncoder for RawPacket {
+    fn packet_id(&self) -> u32 {
+        self.id
     }
 
-    Ok(packet)
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_all(&self.payload)?;
+        Ok(())
+    }
 }
 
 pub trait PacketEncoder {
-    fn encode<W: Write>(&self, stream: &mut W) -> anyhow::Result<()>;
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()>;
     fn packet_id(&self) -> u32;
 }
 
 pub trait PacketDecoder {
-    fn decode<R: Read>(stream: &mut R) -> anyhow::Result<Box<Self>>;
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>>;
     fn packet_id(&self) -> u32;
 }
+
+/// バージョンによって形式が変わるパケット
+pub trait VersionedPacket: Sized {
+    fn encode_versioned<W: Write>(&self, stream: &mut W, protocol: i32) -> Result<()>;
+    fn decode_versioned<R: Read>(stream: &mut R, protocol: i32) -> Result<Self>;
+    fn packet_id(&self) -> u32;
+}
+
+/// `protocol` の形式で送る
+pub struct Versioned<P>(pub P, pub i32);
+
+impl<P: VersionedPacket> PacketEncoder for Versioned<P> {
+    fn packet_id(&self) -> u32 {
+        self.0.packet_id()
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        self.0.encode_versioned(stream, self.1)
+    }
+}
+
+pub(crate) fn write_string<W: Write>(stream: &mut W, s: &str) -> Result<()> {
+    stream.write_varint(s.len() as u32)?;
+    stream.write_all(s.as_bytes())?;
+
+    Ok(())
+}
+
+pub(crate) fn read_string<R: Read>(stream: &mut R) -> Result<String> {
+    let len: u32 = stream.read_varint()?;
+    let mut buf = vec![0_u8; len as usize];
+    stream.read_exact(&mut buf)?;
+
+    Ok(String::from_utf8(buf)?)
+}
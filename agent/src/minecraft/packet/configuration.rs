@@ -0,0 +1,76 @@
+use byteorder::WriteBytesExt;
+use integer_encoding::VarIntWriter;
+use std::io::Write;
+
+use crate::minecraft::Result;
+
+use super::{write_string, PacketEncoder};
+
+// https://wiki.vg/Protocol#Configuration (1.20.5 - 1.21.1)
+
+#[derive(Debug)]
+pub struct KnownPack {
+    pub namespace: String,
+    pub id: String,
+    pub version: String,
+}
+
+#[derive(Debug)]
+pub struct KnownPacks {
+    pub packs: Vec<KnownPack>,
+}
+
+impl PacketEncoder for KnownPacks {
+    fn packet_id(&self) -> u32 {
+        0x0E
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_varint(self.packs.len() as u32)?;
+        for pack in &self.packs {
+            write_string(stream, &pack.namespace)?;
+            write_string(stream, &pack.id)?;
+            write_string(stream, &pack.version)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// エントリのデータはクライアントの Known Pack から読ませるので ID だけ送る
+#[derive(Debug)]
+pub struct RegistryData {
+    pub registry: String,
+    pub entries: Vec<String>,
+}
+
+impl PacketEncoder for RegistryData {
+    fn packet_id(&self) -> u32 {
+        0x07
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_string(stream, &self.registry)?;
+        stream.write_varint(self.entries.len() as u32)?;
+        for entry in &self.entries {
+            write_string(stream, entry)?;
+            // has data
+            stream.write_u8(0)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FinishConfiguration {}
+
+impl PacketEncoder for FinishConfiguration {
+    fn packet_id(&self) -> u32 {
+        0x03
+    }
+
+    fn encode<W: Write>(&self, _stream: &mut W) -> Result<()> {
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use serde::Serialize;
 use std::io::Write;
 
 use super::PacketEncoder;
-use crate::minecraft::raw_json_text::RawJsonText;
+use crate::minecraft::{raw_json_text::RawJsonText, Result};
 
 #[derive(Debug, Serialize)]
 pub struct DisconnectLogin {
@@ -15,7 +15,7 @@ impl PacketEncoder for DisconnectLogin {
         0x00
     }
 
-    fn encode<W: Write>(&self, stream: &mut W) -> anyhow::Result<()> {
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
         let s = serde_json::to_string(&self.reason)?.into_bytes();
         stream.write_varint(s.len() as u32)?;
         stream.write_all(&s)?;
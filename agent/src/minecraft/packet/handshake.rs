@@ -2,6 +2,8 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use integer_encoding::{VarIntReader, VarIntWriter};
 use std::io::{Cursor, Read, Write};
 
+use crate::minecraft::Result;
+
 use super::{PacketDecoder, PacketEncoder};
 
 #[derive(Debug)]
@@ -12,12 +14,37 @@ pub struct Handshake {
     pub next_status: i32,
 }
 
+impl Handshake {
+    /// 振り分けに使うホスト名
+    ///
+    /// Forge は `\0FML\0` などを、BungeeCord の IP 転送は `\0` 区切りで情報を
+    /// host の後ろに付けるので、最初の `\0` より前だけを使う。
+    pub fn hostname(&self) -> String {
+        self.address().trim_end_matches('.').to_ascii_lowercase()
+    }
+
+    /// プレイヤーが入力したとおりのホスト。`\0` 以降の付加情報を除く。
+    ///
+    /// Transfer で接続し直させるときに使う。Forge のクライアントは接続し直すときに
+    /// 自分で `\0FML3\0` などを付け直す。
+    pub fn address(&self) -> &str {
+        self.host.split('\0').next().unwrap_or_default()
+    }
+
+    /// host と port を書き換える。`\0` 以降の付加情報は残す。
+    pub fn rewrite_host(&mut self, host: &str, port: u16) {
+        let suffix = self.host.find('\0').map_or("", |i| &self.host[i..]);
+        self.host = format!("{}{}", host, suffix);
+        self.port = port;
+    }
+}
+
 impl PacketEncoder for Handshake {
     fn packet_id(&self) -> u32 {
         0x00
     }
 
-    fn encode<W: Write>(&self, stream: &mut W) -> anyhow::Result<()> {
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
         stream.write_varint(self.version as u32)?;
 
         let host_bytes = self.host.as_bytes();
@@ -37,7 +64,7 @@ impl PacketDecoder for Handshake {
         0x00
     }
 
-    fn decode<R: Read>(stream: &mut R) -> anyhow::Result<Box<Self>> {
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
         let version: u32 = stream.read_varint()?;
 
         let host_len: u32 = stream.read_varint()?;
@@ -60,3 +87,32 @@ impl PacketDecoder for Handshake {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(host: &str) -> Handshake {
+        Handshake {
+            version: 767,
+            host: host.to_string(),
+            port: 25565,
+            next_status: 2,
+        }
+    }
+
+    #[test]
+    fn strips_forge_marker() {
+        let handshake = handshake("Survival.Example.com.\0FML3\0");
+        assert_eq!(handshake.address(), "Survival.Example.com.");
+        assert_eq!(handshake.hostname(), "survival.example.com");
+    }
+
+    #[test]
+    fn rewrite_host_keeps_suffix() {
+        let mut handshake = handshake("example.com\0FML3\0");
+        handshake.rewrite_host("10.0.0.10", 25566);
+        assert_eq!(handshake.host, "10.0.0.10\0FML3\0");
+        assert_eq!(handshake.port, 25566);
+    }
+}
@@ -0,0 +1,92 @@
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use integer_encoding::{VarIntReader, VarIntWriter};
+use std::io::{Read, Write};
+
+use crate::minecraft::Result;
+
+use super::{read_string, write_string, PacketDecoder, PacketEncoder};
+
+/// ログイン中にサーバが独自のチャンネルで送るメッセージ (Velocity の転送情報など)
+#[derive(Debug, Clone)]
+pub struct LoginPluginRequest {
+    pub message_id: u32,
+    pub channel: String,
+    /// 長さを持たず、パケットの残り全部
+    pub data: Vec<u8>,
+}
+
+/// `data` が `None` ならチャンネルを知らなかったという返事になる
+#[derive(Debug, Clone)]
+pub struct LoginPluginResponse {
+    pub message_id: u32,
+    pub data: Option<Vec<u8>>,
+}
+
+impl PacketEncoder for LoginPluginRequest {
+    fn packet_id(&self) -> u32 {
+        0x04
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_varint(self.message_id)?;
+        write_string(stream, &self.channel)?;
+        stream.write_all(&self.data)?;
+
+        Ok(())
+    }
+}
+
+impl PacketDecoder for LoginPluginRequest {
+    fn packet_id(&self) -> u32 {
+        0x04
+    }
+
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
+        let message_id = stream.read_varint()?;
+        let channel = read_string(stream)?;
+        let mut data = vec![];
+        stream.read_to_end(&mut data)?;
+
+        Ok(Box::new(LoginPluginRequest {
+            message_id,
+            channel,
+            data,
+        }))
+    }
+}
+
+impl PacketEncoder for LoginPluginResponse {
+    fn packet_id(&self) -> u32 {
+        0x02
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_varint(self.message_id)?;
+        stream.write_u8(self.data.is_some() as u8)?;
+        if let Some(data) = &self.data {
+            stream.write_all(data)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketDecoder for LoginPluginResponse {
+    fn packet_id(&self) -> u32 {
+        0x02
+    }
+
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
+        let message_id = stream.read_varint()?;
+        let data = match stream.read_u8()? {
+            0 => None,
+            _ => {
+                let mut data = vec![];
+                stream.read_to_end(&mut data)?;
+                Some(data)
+            }
+        };
+
+        Ok(Box::new(LoginPluginResponse { message_id, data }))
+    }
+}
@@ -0,0 +1,182 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use integer_encoding::VarIntReader;
+use std::io::{Read, Write};
+
+use crate::minecraft::{version, Result};
+
+use super::{read_string, write_string, VersionedPacket};
+
+/// ログイン開始
+///
+/// - 1.19 より前: 名前のみ
+/// - 1.19, 1.19.1/2: 署名用の公開鍵が任意で付く (読み飛ばす)。1.19.1 からは UUID も任意で付く
+/// - 1.19.3 から 1.20.1: UUID が任意で付く
+/// - 1.20.2 以降: UUID が必ず付く
+#[derive(Debug, Clone)]
+pub struct LoginStart {
+    pub name: String,
+    pub uuid: Option<uuid::Uuid>,
+}
+
+impl VersionedPacket for LoginStart {
+    fn packet_id(&self) -> u32 {
+        0x00
+    }
+
+    fn decode_versioned<R: Read>(stream: &mut R, protocol: i32) -> Result<Self> {
+        let name = read_string(stream)?;
+
+        if (version::V1_19..version::V1_19_3).contains(&protocol) && stream.read_u8()? != 0 {
+            // timestamp, 公開鍵, 署名
+            stream.read_i64::<BigEndian>()?;
+            skip_bytes(stream)?;
+            skip_bytes(stream)?;
+        }
+
+        let has_uuid = match protocol {
+            p if version::V1_20_2 <= p => true,
+            p if version::V1_19_1 <= p => stream.read_u8()? != 0,
+            _ => false,
+        };
+        let uuid = has_uuid.then(|| read_uuid(stream)).transpose()?;
+
+        Ok(LoginStart { name, uuid })
+    }
+
+    /// 署名は送らない
+    fn encode_versioned<W: Write>(&self, stream: &mut W, protocol: i32) -> Result<()> {
+        write_string(stream, &self.name)?;
+
+        if (version::V1_19..version::V1_19_3).contains(&protocol) {
+            stream.write_u8(0)?;
+        }
+
+        if version::V1_20_2 <= protocol {
+            stream.write_u128::<BigEndian>(self.uuid.unwrap_or_default().as_u128())?;
+        } else if version::V1_19_1 <= protocol {
+            stream.write_u8(self.uuid.is_some() as u8)?;
+            if let Some(uuid) = self.uuid {
+                stream.write_u128::<BigEndian>(uuid.as_u128())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn read_uuid<R: Read>(stream: &mut R) -> Result<uuid::Uuid> {
+    Ok(uuid::Uuid::from_u128(stream.read_u128::<BigEndian>()?))
+}
+
+fn skip_bytes<R: Read>(stream: &mut R) -> Result<()> {
+    let len: u32 = stream.read_varint()?;
+    let mut buf = vec![0_u8; len as usize];
+    stream.read_exact(&mut buf)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: u128 = 0x0123_4567_89ab_cdef_0123_4567_89ab_cdef;
+
+    fn name() -> Vec<u8> {
+        let mut buf = vec![5];
+        buf.extend(b"Steve");
+        buf
+    }
+
+    fn decode(bytes: &[u8], protocol: i32) -> LoginStart {
+        let mut stream = bytes;
+        let packet = LoginStart::decode_versioned(&mut stream, protocol).unwrap();
+        assert!(stream.is_empty(), "{} bytes left", stream.len());
+        packet
+    }
+
+    fn encode(packet: &LoginStart, protocol: i32) -> Vec<u8> {
+        let mut buf = vec![];
+        packet.encode_versioned(&mut buf, protocol).unwrap();
+        buf
+    }
+
+    fn login_start(uuid: Option<u128>) -> LoginStart {
+        LoginStart {
+            name: "Steve".to_string(),
+            uuid: uuid.map(uuid::Uuid::from_u128),
+        }
+    }
+
+    #[test]
+    fn name_only_before_1_19() {
+        let packet = decode(&name(), version::V1_19 - 1);
+        assert_eq!(packet.name, "Steve");
+        assert_eq!(packet.uuid, None);
+        assert_eq!(encode(&login_start(Some(UUID)), version::V1_16), name());
+    }
+
+    #[test]
+    fn skips_signature_data_on_1_19() {
+        let mut bytes = name();
+        bytes.push(1);
+        bytes.extend(42_i64.to_be_bytes());
+        bytes.extend([3, 1, 2, 3]);
+        bytes.extend([2, 4, 5]);
+
+        let packet = decode(&bytes, version::V1_19);
+        assert_eq!(packet.name, "Steve");
+        assert_eq!(packet.uuid, None);
+
+        let mut expected = name();
+        expected.push(0);
+        assert_eq!(encode(&login_start(Some(UUID)), version::V1_19), expected);
+    }
+
+    #[test]
+    fn optional_uuid_after_signature_on_1_19_1() {
+        let mut bytes = name();
+        bytes.push(0);
+        bytes.push(1);
+        bytes.extend(UUID.to_be_bytes());
+
+        let packet = decode(&bytes, version::V1_19_1);
+        assert_eq!(packet.uuid, Some(uuid::Uuid::from_u128(UUID)));
+        assert_eq!(encode(&packet, version::V1_19_1), bytes);
+    }
+
+    #[test]
+    fn optional_uuid_from_1_19_3() {
+        for protocol in version::V1_19_3..version::V1_20_2 {
+            let mut bytes = name();
+            bytes.push(1);
+            bytes.extend(UUID.to_be_bytes());
+            let packet = decode(&bytes, protocol);
+            assert_eq!(packet.uuid, Some(uuid::Uuid::from_u128(UUID)));
+            assert_eq!(encode(&packet, protocol), bytes);
+
+            let mut bytes = name();
+            bytes.push(0);
+            let packet = decode(&bytes, protocol);
+            assert_eq!(packet.uuid, None);
+            assert_eq!(encode(&packet, protocol), bytes);
+        }
+    }
+
+    #[test]
+    fn mandatory_uuid_from_1_20_2() {
+        let mut bytes = name();
+        bytes.extend(UUID.to_be_bytes());
+
+        for protocol in [version::V1_20_2, version::V1_21, version::LATEST] {
+            let packet = decode(&bytes, protocol);
+            assert_eq!(packet.uuid, Some(uuid::Uuid::from_u128(UUID)));
+            assert_eq!(encode(&packet, protocol), bytes);
+        }
+
+        // UUID が無ければ nil UUID を送る
+        let mut expected = name();
+        expected.extend([0; 16]);
+        assert_eq!(encode(&login_start(None), version::V1_21), expected);
+    }
+}
@@ -0,0 +1,197 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use integer_encoding::{VarIntReader, VarIntWriter};
+use std::io::{Read, Write};
+
+use crate::minecraft::{version, Error, Result};
+
+use super::{login_start::read_uuid, read_string, write_string, VersionedPacket};
+
+/// ログイン完了
+///
+/// - 1.16 より前: UUID はハイフン付きの文字列
+/// - 1.19 以降: スキンなどのプロパティが付く
+/// - 1.20.5 から 1.21.1: strict error handling (常に false で送る)
+#[derive(Debug, Clone)]
+pub struct LoginSuccess {
+    pub uuid: uuid::Uuid,
+    pub username: String,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl VersionedPacket for LoginSuccess {
+    fn packet_id(&self) -> u32 {
+        0x02
+    }
+
+    fn encode_versioned<W: Write>(&self, stream: &mut W, protocol: i32) -> Result<()> {
+        if protocol < version::V1_16 {
+            write_string(stream, &self.uuid.hyphenated().to_string())?;
+        } else {
+            stream.write_u128::<BigEndian>(self.uuid.as_u128())?;
+        }
+        write_string(stream, &self.username)?;
+
+        if version::V1_19 <= protocol {
+            stream.write_varint(self.properties.len() as u32)?;
+            for property in &self.properties {
+                write_string(stream, &property.name)?;
+                write_string(stream, &property.value)?;
+                stream.write_u8(property.signature.is_some() as u8)?;
+                if let Some(signature) = &property.signature {
+                    write_string(stream, signature)?;
+                }
+            }
+        }
+
+        if (version::V1_20_5..version::V1_21_2).contains(&protocol) {
+            stream.write_u8(0)?;
+        }
+
+        Ok(())
+    }
+
+    fn decode_versioned<R: Read>(stream: &mut R, protocol: i32) -> Result<Self> {
+        let uuid = if protocol < version::V1_16 {
+            let uuid = read_string(stream)?;
+            uuid::Uuid::parse_str(&uuid).map_err(|e| Error::protocol(e.to_string()))?
+        } else {
+            read_uuid(stream)?
+        };
+        let username = read_string(stream)?;
+
+        let mut properties = vec![];
+        if version::V1_19 <= protocol {
+            let count: u32 = stream.read_varint()?;
+            for _ in 0..count {
+                let name = read_string(stream)?;
+                let value = read_string(stream)?;
+                let signature = match stream.read_u8()? {
+                    0 => None,
+                    _ => Some(read_string(stream)?),
+                };
+                properties.push(Property {
+                    name,
+                    value,
+                    signature,
+                });
+            }
+        }
+
+        if (version::V1_20_5..version::V1_21_2).contains(&protocol) {
+            stream.read_u8()?;
+        }
+
+        Ok(LoginSuccess {
+            uuid,
+            username,
+            properties,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "01234567-89ab-cdef-0123-456789abcdef";
+
+    fn login_success() -> LoginSuccess {
+        LoginSuccess {
+            uuid: uuid::Uuid::parse_str(UUID).unwrap(),
+            username: "Steve".to_string(),
+            properties: vec![Property {
+                name: "textures".to_string(),
+                value: "e30=".to_string(),
+                signature: Some("c2ln".to_string()),
+            }],
+        }
+    }
+
+    fn encode(protocol: i32) -> Vec<u8> {
+        let mut buf = vec![];
+        login_success()
+            .encode_versioned(&mut buf, protocol)
+            .unwrap();
+        buf
+    }
+
+    fn round_trip(protocol: i32) -> LoginSuccess {
+        let bytes = encode(protocol);
+        let mut stream = bytes.as_slice();
+        let packet = LoginSuccess::decode_versioned(&mut stream, protocol).unwrap();
+        assert!(stream.is_empty(), "{} bytes left", stream.len());
+        packet
+    }
+
+    fn username() -> Vec<u8> {
+        let mut buf = vec![5];
+        buf.extend(b"Steve");
+        buf
+    }
+
+    fn properties() -> Vec<u8> {
+        let mut buf = vec![1, 8];
+        buf.extend(b"textures");
+        buf.push(4);
+        buf.extend(b"e30=");
+        buf.extend([1, 4]);
+        buf.extend(b"c2ln");
+        buf
+    }
+
+    #[test]
+    fn string_uuid_before_1_16() {
+        let mut expected = vec![36];
+        expected.extend(UUID.as_bytes());
+        expected.extend(username());
+        assert_eq!(encode(version::V1_16 - 1), expected);
+
+        let packet = round_trip(version::V1_16 - 1);
+        assert_eq!(packet.uuid.to_string(), UUID);
+        assert!(packet.properties.is_empty());
+    }
+
+    #[test]
+    fn binary_uuid_from_1_16() {
+        let uuid = uuid::Uuid::parse_str(UUID).unwrap();
+        let mut expected = uuid.as_u128().to_be_bytes().to_vec();
+        expected.extend(username());
+        assert_eq!(encode(version::V1_16), expected);
+        assert_eq!(encode(version::V1_19 - 1), expected);
+    }
+
+    #[test]
+    fn properties_from_1_19() {
+        let uuid = uuid::Uuid::parse_str(UUID).unwrap();
+        let mut expected = uuid.as_u128().to_be_bytes().to_vec();
+        expected.extend(username());
+        expected.extend(properties());
+        assert_eq!(encode(version::V1_19), expected);
+        assert_eq!(encode(version::V1_20_5 - 1), expected);
+
+        let packet = round_trip(version::V1_19);
+        assert_eq!(packet.properties.len(), 1);
+        assert_eq!(packet.properties[0].name, "textures");
+        assert_eq!(packet.properties[0].signature.as_deref(), Some("c2ln"));
+    }
+
+    #[test]
+    fn strict_error_handling_only_on_1_20_5_to_1_21_1() {
+        let without = encode(version::V1_20_5 - 1);
+        for protocol in [version::V1_20_5, version::V1_21] {
+            let bytes = encode(protocol);
+            assert_eq!(bytes[..without.len()], without);
+            assert_eq!(bytes[without.len()..], [0]);
+            round_trip(protocol);
+        }
+        assert_eq!(encode(version::V1_21_2), without);
+        round_trip(version::V1_21_2);
+    }
+}
@@ -1,16 +1,20 @@
-use byteorder::WriteBytesExt;
-use std::{
-    io::{Read, Write},
-    time::{SystemTime, UNIX_EPOCH},
-};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
 
-use byteorder::LittleEndian;
+use crate::minecraft::Result;
 
 use super::{PacketDecoder, PacketEncoder};
 
+/// Ping Request (serverbound)。payload はそのまま Pong で返される。
 #[derive(Debug)]
 pub struct Ping {
-    payload: u64,
+    pub payload: i64,
+}
+
+/// Pong Response (clientbound)
+#[derive(Debug)]
+pub struct Pong {
+    pub payload: i64,
 }
 
 impl PacketEncoder for Ping {
@@ -18,8 +22,8 @@ impl PacketEncoder for Ping {
         0x01
     }
 
-    fn encode<W: Write>(&self, stream: &mut W) -> anyhow::Result<()> {
-        stream.write_u64::<LittleEndian>(self.payload)?;
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_i64::<BigEndian>(self.payload)?;
 
         Ok(())
     }
@@ -30,14 +34,71 @@ impl PacketDecoder for Ping {
         0x01
     }
 
-    fn decode<R: Read>(_stream: &mut R) -> anyhow::Result<Box<Self>> {
-        let start = SystemTime::now();
-        let now = start
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards");
-
-        let payload = now.as_secs();
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
+        let payload = stream.read_i64::<BigEndian>()?;
 
         Ok(Box::new(Ping { payload }))
     }
 }
+
+impl PacketEncoder for Pong {
+    fn packet_id(&self) -> u32 {
+        0x01
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_i64::<BigEndian>(self.payload)?;
+
+        Ok(())
+    }
+}
+
+impl PacketDecoder for Pong {
+    fn packet_id(&self) -> u32 {
+        0x01
+    }
+
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
+        let payload = stream.read_i64::<BigEndian>()?;
+
+        Ok(Box::new(Pong { payload }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::minecraft::{packet::RawPacket, Error};
+
+    const PAYLOAD: i64 = 0x0102_0304_0506_0708;
+
+    #[test]
+    fn payload_is_big_endian() {
+        let mut buf = vec![];
+        Ping { payload: PAYLOAD }.encode(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut buf = vec![];
+        Pong { payload: -2 }.encode(&mut buf).unwrap();
+        assert_eq!(buf, [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn decodes_payload() {
+        let packet = RawPacket {
+            id: 0x01,
+            payload: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        assert_eq!(packet.decode::<Ping>().unwrap().payload, PAYLOAD);
+        assert_eq!(packet.decode::<Pong>().unwrap().payload, PAYLOAD);
+    }
+
+    #[test]
+    fn short_payload_is_protocol_error() {
+        let packet = RawPacket {
+            id: 0x01,
+            payload: vec![1, 2, 3],
+        };
+        assert!(matches!(packet.decode::<Pong>(), Err(Error::Protocol(_))));
+    }
+}
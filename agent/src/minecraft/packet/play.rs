@@ -0,0 +1,224 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use integer_encoding::VarIntWriter;
+use std::io::Write;
+
+use super::{write_string, PacketEncoder};
+use crate::minecraft::{nbt::write_text_component, raw_json_text::RawJsonText, Result};
+
+// https://wiki.vg/Protocol (1.20.5 - 1.21.1)
+// Limbo で使う最低限のパケットのみ
+
+#[derive(Debug)]
+pub struct Login {
+    pub entity_id: i32,
+    pub dimension_type: u32,
+    pub dimension_name: String,
+    pub view_distance: u32,
+    pub game_mode: u8,
+}
+
+impl PacketEncoder for Login {
+    fn packet_id(&self) -> u32 {
+        0x2B
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_i32::<BigEndian>(self.entity_id)?;
+        // is hardcore
+        stream.write_u8(0)?;
+        stream.write_varint(1_u32)?;
+        write_string(stream, &self.dimension_name)?;
+        // max players
+        stream.write_varint(1_u32)?;
+        stream.write_varint(self.view_distance)?;
+        // simulation distance
+        stream.write_varint(self.view_distance)?;
+        // reduced debug info, enable respawn screen, do limited crafting
+        stream.write_all(&[0, 1, 0])?;
+        stream.write_varint(self.dimension_type)?;
+        write_string(stream, &self.dimension_name)?;
+        // hashed seed
+        stream.write_i64::<BigEndian>(0)?;
+        stream.write_u8(self.game_mode)?;
+        // previous game mode
+        stream.write_i8(-1)?;
+        // is debug, is flat, has death location
+        stream.write_all(&[0, 1, 0])?;
+        // portal cooldown
+        stream.write_varint(0_u32)?;
+        // enforces secure chat
+        stream.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SynchronizePlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub teleport_id: u32,
+}
+
+impl PacketEncoder for SynchronizePlayerPosition {
+    fn packet_id(&self) -> u32 {
+        0x40
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_f64::<BigEndian>(self.x)?;
+        stream.write_f64::<BigEndian>(self.y)?;
+        stream.write_f64::<BigEndian>(self.z)?;
+        // yaw, pitch
+        stream.write_f32::<BigEndian>(0.0)?;
+        stream.write_f32::<BigEndian>(0.0)?;
+        // flags (すべて絶対座標)
+        stream.write_u8(0)?;
+        stream.write_varint(self.teleport_id)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct GameEvent {
+    pub event: u8,
+    pub value: f32,
+}
+
+impl GameEvent {
+    pub const START_WAITING_FOR_LEVEL_CHUNKS: u8 = 13;
+}
+
+impl PacketEncoder for GameEvent {
+    fn packet_id(&self) -> u32 {
+        0x22
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_u8(self.event)?;
+        stream.write_f32::<BigEndian>(self.value)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct KeepAlive {
+    pub id: i64,
+}
+
+impl PacketEncoder for KeepAlive {
+    fn packet_id(&self) -> u32 {
+        0x26
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_i64::<BigEndian>(self.id)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct SetActionBarText {
+    pub text: RawJsonText,
+}
+
+impl PacketEncoder for SetActionBarText {
+    fn packet_id(&self) -> u32 {
+        0x4C
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_text_component(stream, &self.text)
+    }
+}
+
+#[derive(Debug)]
+pub struct SetTitleText {
+    pub text: RawJsonText,
+}
+
+impl PacketEncoder for SetTitleText {
+    fn packet_id(&self) -> u32 {
+        0x65
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_text_component(stream, &self.text)
+    }
+}
+
+#[derive(Debug)]
+pub struct SetSubtitleText {
+    pub text: RawJsonText,
+}
+
+impl PacketEncoder for SetSubtitleText {
+    fn packet_id(&self) -> u32 {
+        0x63
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_text_component(stream, &self.text)
+    }
+}
+
+/// 単位は tick
+#[derive(Debug)]
+pub struct SetTitleAnimationTimes {
+    pub fade_in: i32,
+    pub stay: i32,
+    pub fade_out: i32,
+}
+
+impl PacketEncoder for SetTitleAnimationTimes {
+    fn packet_id(&self) -> u32 {
+        0x66
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_i32::<BigEndian>(self.fade_in)?;
+        stream.write_i32::<BigEndian>(self.stay)?;
+        stream.write_i32::<BigEndian>(self.fade_out)?;
+
+        Ok(())
+    }
+}
+
+/// 1.20.5 以降。クライアントは指定先に接続し直す。
+#[derive(Debug)]
+pub struct Transfer {
+    pub host: String,
+    pub port: u16,
+}
+
+impl PacketEncoder for Transfer {
+    fn packet_id(&self) -> u32 {
+        0x73
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_string(stream, &self.host)?;
+        stream.write_varint(self.port as u32)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Disconnect {
+    pub reason: RawJsonText,
+}
+
+impl PacketEncoder for Disconnect {
+    fn packet_id(&self) -> u32 {
+        0x1D
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        write_text_component(stream, &self.reason)
+    }
+}
@@ -0,0 +1,36 @@
+use integer_encoding::{VarIntReader, VarIntWriter};
+use std::io::{Read, Write};
+
+use crate::minecraft::Result;
+
+use super::{PacketDecoder, PacketEncoder};
+
+/// これ以降、`threshold` バイト以上のパケットは圧縮される。負の値なら圧縮しない。
+#[derive(Debug, Clone)]
+pub struct SetCompression {
+    pub threshold: i32,
+}
+
+impl PacketEncoder for SetCompression {
+    fn packet_id(&self) -> u32 {
+        0x03
+    }
+
+    fn encode<W: Write>(&self, stream: &mut W) -> Result<()> {
+        stream.write_varint(self.threshold as u32)?;
+        Ok(())
+    }
+}
+
+impl PacketDecoder for SetCompression {
+    fn packet_id(&self) -> u32 {
+        0x03
+    }
+
+    fn decode<R: Read>(stream: &mut R) -> Result<Box<Self>> {
+        let threshold: u32 = stream.read_varint()?;
+        Ok(Box::new(SetCompression {
+            threshold: threshold as i32,
+        }))
+    }
+}
@@ -1,5 +1,7 @@
 use std::io::{Read, Write};
 
+use crate::minecraft::Result;
+
 use super::{PacketDecoder, PacketEncoder};
 
 #[derive(Debug)]
@@ -10,7 +12,7 @@ impl PacketEncoder for StatusRequest {
         0x00
     }
 
-    fn encode<W: Write>(&self, _stream: &mut W) -> anyhow::Result<()> {
+    fn encode<W: Write>(&self, _stream: &mut W) -> Result<()> {
         Ok(())
     }
 }
@@ -20,7 +22,7 @@ impl PacketDecoder for StatusRequest {
         0x00
     }
 
-    fn decode<R: Read>(_stream: &mut R) -> anyhow::Result<Box<Self>> {
+    fn decode<R: Read>(_stream: &mut R) -> Result<Box<Self>> {
         Ok(Box::new(StatusRequest {}))
     }
 }
@@ -1,17 +1,25 @@
 use integer_encoding::VarIntWriter;
 use serde::{Deserialize, Serialize};
 
-use crate::minecraft::raw_json_text::RawJsonText;
+use crate::minecraft::{
+    favicon::{self, Favicon},
+    raw_json_text::RawJsonText,
+    Result,
+};
 
-use super::PacketEncoder;
+use super::{read_string, PacketDecoder, PacketEncoder};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StatusResponse {
     pub version: Version,
     pub players: Players,
     pub description: RawJsonText,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub favicon: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "favicon::deserialize_lenient"
+    )]
+    pub favicon: Option<Favicon>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub modinfo: Option<Modinfo>,
 }
@@ -48,7 +56,7 @@ impl PacketEncoder for StatusResponse {
         0x00
     }
 
-    fn encode<W: std::io::Write>(&self, stream: &mut W) -> anyhow::Result<()> {
+    fn encode<W: std::io::Write>(&self, stream: &mut W) -> Result<()> {
         let s = serde_json::to_string(&self)?.into_bytes();
         stream.write_varint(s.len() as u32)?;
         stream.write_all(&s)?;
@@ -56,3 +64,14 @@ impl PacketEncoder for StatusResponse {
         Ok(())
     }
 }
+
+impl PacketDecoder for StatusResponse {
+    fn packet_id(&self) -> u32 {
+        0x00
+    }
+
+    fn decode<R: std::io::Read>(stream: &mut R) -> Result<Box<Self>> {
+        let s = read_string(stream)?;
+        Ok(Box::new(serde_json::from_str(&s)?))
+    }
+}
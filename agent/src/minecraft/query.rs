@@ -0,0 +1,565 @@
+//! UDP の Query プロトコル (GameSpy4)
+//!
+//! https://minecraft.wiki/w/Query
+//!
+//! server.properties で `enable-query=true` にしたサーバが `query.port` で応答する。
+//! ステータスと違い、全員のプレイヤー名やプラグイン、ワールド名が分かる。
+//!
+//! 要求を送る [`Query`] のほか、プロキシが代わりに応答するための [`Request`] などもある。
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+use tokio::{
+    net::{self, UdpSocket},
+    time,
+};
+
+use super::{Error, Result};
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const HANDSHAKE: u8 = 9;
+const STAT: u8 = 0;
+/// 1.7 以降のサーバは session id の各バイトの上位 4 ビットを無視する
+const SESSION_MASK: i32 = 0x0F0F_0F0F;
+/// full stat の key-value の前に付く固定の 11 バイト
+const FULL_STAT_PADDING: &[u8] = b"splitnum\x00\x80\x00";
+/// プレイヤー一覧の前に付く固定の 10 バイト
+const PLAYERS_PADDING: &[u8] = b"\x01player_\x00\x00";
+/// チャレンジトークンが変わる間隔
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BasicStat {
+    pub motd: String,
+    pub game_type: String,
+    pub map: String,
+    pub num_players: usize,
+    pub max_players: usize,
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FullStat {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    /// `Paper on 1.21: WorldEdit 7.3; LuckPerms 5.4` のようにサーバ名とプラグインが並ぶ。
+    /// バニラでは空。
+    pub plugins: String,
+    pub map: String,
+    pub num_players: usize,
+    pub max_players: usize,
+    pub host_port: u16,
+    pub host_ip: String,
+    /// ステータスの sample と違い全員分
+    pub players: Vec<String>,
+}
+
+impl FullStat {
+    /// `plugins` をサーバ名とプラグインに分ける
+    pub fn plugin_list(&self) -> (Option<&str>, Vec<&str>) {
+        match self.plugins.split_once(':') {
+            Some((software, plugins)) => (
+                Some(software.trim()),
+                plugins
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .collect(),
+            ),
+            None if self.plugins.is_empty() => (None, vec![]),
+            None => (Some(self.plugins.trim()), vec![]),
+        }
+    }
+
+    pub fn basic(&self) -> BasicStat {
+        BasicStat {
+            motd: self.motd.clone(),
+            game_type: self.game_type.clone(),
+            map: self.map.clone(),
+            num_players: self.num_players,
+            max_players: self.max_players,
+            host_port: self.host_port,
+            host_ip: self.host_ip.clone(),
+        }
+    }
+}
+
+pub struct Query {
+    socket: UdpSocket,
+    session_id: i32,
+}
+
+/// 名前を解決して、その相手とだけやり取りする UDP ソケットを作る
+pub async fn connect_socket(host: &str, port: u16) -> Result<UdpSocket> {
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    let addr = time::timeout(TIMEOUT, net::lookup_host((host, port)))
+        .await??
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} did not resolve to any address", host),
+            )
+        })?;
+
+    let bind: SocketAddr = match addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0_u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+
+    Ok(socket)
+}
+
+impl Query {
+    pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let socket = connect_socket(host, port).await?;
+        let session_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as i32)
+            .unwrap_or(1)
+            & SESSION_MASK;
+
+        Ok(Self { socket, session_id })
+    }
+
+    pub async fn basic_stat(&mut self) -> Result<BasicStat> {
+        let token = self.handshake().await?;
+        let response = self.request(STAT, &token.to_be_bytes()).await?;
+        let mut reader = Reader(&response);
+
+        let motd = reader.string()?;
+        let game_type = reader.string()?;
+        let map = reader.string()?;
+        let num_players = reader.number()?;
+        let max_players = reader.number()?;
+        let host_port = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+        let host_ip = reader.string()?;
+
+        Ok(BasicStat {
+            motd,
+            game_type,
+            map,
+            num_players,
+            max_players,
+            host_port,
+            host_ip,
+        })
+    }
+
+    pub async fn full_stat(&mut self) -> Result<FullStat> {
+        let token = self.handshake().await?;
+        let mut payload = token.to_be_bytes().to_vec();
+        payload.extend([0; 4]);
+        let response = self.request(STAT, &payload).await?;
+        let mut reader = Reader(&response);
+
+        reader.expect(FULL_STAT_PADDING)?;
+        let mut values = vec![];
+        loop {
+            let key = reader.string()?;
+            if key.is_empty() {
+                break;
+            }
+            values.push((key, reader.string()?));
+        }
+        let value = |key: &str| {
+            values
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        let number = |key: &str| {
+            value(key)
+                .parse::<usize>()
+                .map_err(|_| Error::protocol(format!("invalid {} in full stat", key)))
+        };
+
+        reader.expect(PLAYERS_PADDING)?;
+        let mut players = vec![];
+        loop {
+            let player = reader.string()?;
+            if player.is_empty() {
+                break;
+            }
+            players.push(player);
+        }
+
+        Ok(FullStat {
+            motd: value("hostname"),
+            game_type: value("gametype"),
+            game_id: value("game_id"),
+            version: value("version"),
+            plugins: value("plugins"),
+            map: value("map"),
+            num_players: number("numplayers")?,
+            max_players: number("maxplayers")?,
+            host_port: number("hostport")?
+                .try_into()
+                .map_err(|_| Error::protocol("invalid hostport in full stat"))?,
+            host_ip: value("hostip"),
+            players,
+        })
+    }
+
+    /// 以降の要求に使うトークンを受け取る。トークンは 30 秒ごとに変わる。
+    async fn handshake(&mut self) -> Result<i32> {
+        let response = self.request(HANDSHAKE, &[]).await?;
+        let token = Reader(&response).string()?;
+
+        token
+            .parse()
+            .map_err(|_| Error::protocol(format!("invalid challenge token: {}", token)))
+    }
+
+    /// 送った種類と session id で始まる応答の残りを返す
+    async fn request(&mut self, kind: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut packet = MAGIC.to_vec();
+        packet.push(kind);
+        packet.extend(self.session_id.to_be_bytes());
+        packet.extend(payload);
+        self.socket.send(&packet).await?;
+
+        let mut buf = vec![0_u8; 65535];
+        loop {
+            let len = time::timeout(TIMEOUT, self.socket.recv(&mut buf)).await??;
+            let response = &buf[..len];
+            // 前の要求への遅れた応答は捨てる
+            if 5 <= len && response[0] == kind && response[1..5] == self.session_id.to_be_bytes() {
+                return Ok(response[5..].to_vec());
+            }
+        }
+    }
+}
+
+/// 応答する側が受け取る要求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Handshake {
+        session_id: i32,
+    },
+    BasicStat {
+        session_id: i32,
+        token: i32,
+    },
+    /// basic stat の後ろに 4 バイトのパディングが付く
+    FullStat {
+        session_id: i32,
+        token: i32,
+    },
+}
+
+impl Request {
+    /// Query の要求でなければ `None`
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let rest = packet.strip_prefix(&MAGIC)?;
+        let (&kind, rest) = rest.split_first()?;
+        let (session_id, rest) = rest.split_first_chunk::<4>()?;
+        let session_id = i32::from_be_bytes(*session_id);
+
+        match kind {
+            HANDSHAKE => Some(Request::Handshake { session_id }),
+            STAT => {
+                let (token, rest) = rest.split_first_chunk::<4>()?;
+                let token = i32::from_be_bytes(*token);
+                match rest.len() {
+                    0 => Some(Request::BasicStat { session_id, token }),
+                    4 => Some(Request::FullStat { session_id, token }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// 送信元のアドレスごとのチャレンジトークン
+///
+/// トークンを確かめずに stat を返すと、送信元を偽った要求で大きな応答を
+/// 第三者に送りつけられる。サーバと同じく 30 秒ごとに変え、直前のものまで受け付ける。
+#[derive(Default)]
+pub struct ChallengeTokens {
+    state: RandomState,
+}
+
+impl ChallengeTokens {
+    pub fn issue(&self, addr: SocketAddr) -> i32 {
+        self.token(addr, Self::window())
+    }
+
+    pub fn verify(&self, addr: SocketAddr, token: i32) -> bool {
+        let window = Self::window();
+        token == self.token(addr, window) || token == self.token(addr, window.wrapping_sub(1))
+    }
+
+    fn window() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() / TOKEN_LIFETIME.as_secs())
+    }
+
+    /// クライアントによっては符号なしとして読むので、負にならないようにする
+    fn token(&self, addr: SocketAddr, window: u64) -> i32 {
+        (self.state.hash_one((addr, window)) & 0x7FFF_FFFF) as i32
+    }
+}
+
+fn response(kind: u8, session_id: i32) -> Vec<u8> {
+    let mut packet = vec![kind];
+    packet.extend(session_id.to_be_bytes());
+    packet
+}
+
+fn push_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend(s.as_bytes());
+    packet.push(0);
+}
+
+pub fn encode_handshake(session_id: i32, token: i32) -> Vec<u8> {
+    let mut packet = response(HANDSHAKE, session_id);
+    push_string(&mut packet, &token.to_string());
+    packet
+}
+
+impl BasicStat {
+    pub fn encode(&self, session_id: i32) -> Vec<u8> {
+        let mut packet = response(STAT, session_id);
+        push_string(&mut packet, &self.motd);
+        push_string(&mut packet, &self.game_type);
+        push_string(&mut packet, &self.map);
+        push_string(&mut packet, &self.num_players.to_string());
+        push_string(&mut packet, &self.max_players.to_string());
+        packet.extend(self.host_port.to_le_bytes());
+        push_string(&mut packet, &self.host_ip);
+        packet
+    }
+}
+
+impl FullStat {
+    pub fn encode(&self, session_id: i32) -> Vec<u8> {
+        let mut packet = response(STAT, session_id);
+        packet.extend(FULL_STAT_PADDING);
+        for (key, value) in [
+            ("hostname", self.motd.as_str()),
+            ("gametype", &self.game_type),
+            ("game_id", &self.game_id),
+            ("version", &self.version),
+            ("plugins", &self.plugins),
+            ("map", &self.map),
+            ("numplayers", &self.num_players.to_string()),
+            ("maxplayers", &self.max_players.to_string()),
+            ("hostport", &self.host_port.to_string()),
+            ("hostip", &self.host_ip),
+        ] {
+            push_string(&mut packet, key);
+            push_string(&mut packet, value);
+        }
+        packet.push(0);
+
+        packet.extend(PLAYERS_PADDING);
+        for player in &self.players {
+            push_string(&mut packet, player);
+        }
+        packet.push(0);
+        packet
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.0.len() < len {
+            return Err(Error::protocol("query response is truncated"));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn expect(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.take(bytes.len())? != bytes {
+            return Err(Error::protocol("unexpected query response"));
+        }
+        Ok(())
+    }
+
+    /// NUL 終端の文字列
+    fn string(&mut self) -> Result<String> {
+        let end = self
+            .0
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| Error::protocol("query response is truncated"))?;
+        let s = String::from_utf8_lossy(&self.0[..end]).into_owned();
+        self.0 = &self.0[end + 1..];
+        Ok(s)
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        let s = self.string()?;
+        s.parse()
+            .map_err(|_| Error::protocol(format!("invalid number: {}", s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_stat() -> FullStat {
+        FullStat {
+            motd: "A Minecraft Server".to_string(),
+            game_type: "SMP".to_string(),
+            game_id: "MINECRAFT".to_string(),
+            version: "1.21.1".to_string(),
+            plugins: "Paper on 1.21.1: WorldEdit 7.3; LuckPerms 5.4".to_string(),
+            map: "world".to_string(),
+            num_players: 2,
+            max_players: 20,
+            host_port: 25565,
+            host_ip: "127.0.0.1".to_string(),
+            players: vec!["Steve".to_string(), "Alex".to_string()],
+        }
+    }
+
+    /// トークンを確かめて `stat` を返すサーバ。ポートを返す。
+    async fn responder(stat: FullStat) -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let tokens = ChallengeTokens::default();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 1500];
+            while let Ok((len, addr)) = socket.recv_from(&mut buf).await {
+                let response = match Request::parse(&buf[..len]) {
+                    Some(Request::Handshake { session_id }) => {
+                        encode_handshake(session_id, tokens.issue(addr))
+                    }
+                    Some(Request::BasicStat { session_id, token })
+                        if tokens.verify(addr, token) =>
+                    {
+                        stat.basic().encode(session_id)
+                    }
+                    Some(Request::FullStat { session_id, token }) if tokens.verify(addr, token) => {
+                        stat.encode(session_id)
+                    }
+                    _ => continue,
+                };
+                socket.send_to(&response, addr).await.unwrap();
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn parses_requests() {
+        let session = [0x00, 0x00, 0x00, 0x01];
+        let token = 9513307_i32.to_be_bytes();
+        let request = |kind: u8, rest: &[u8]| {
+            let mut packet = vec![0xFE, 0xFD, kind];
+            packet.extend(session);
+            packet.extend(rest);
+            Request::parse(&packet)
+        };
+
+        assert_eq!(
+            request(HANDSHAKE, &[]),
+            Some(Request::Handshake { session_id: 1 })
+        );
+        assert_eq!(
+            request(STAT, &token),
+            Some(Request::BasicStat {
+                session_id: 1,
+                token: 9513307
+            })
+        );
+        assert_eq!(
+            request(STAT, &[&token[..], &[0; 4]].concat()),
+            Some(Request::FullStat {
+                session_id: 1,
+                token: 9513307
+            })
+        );
+        // トークンが途中で切れている、パディングの長さが違う
+        assert_eq!(request(STAT, &token[..2]), None);
+        assert_eq!(request(STAT, &[&token[..], &[0; 2]].concat()), None);
+        assert_eq!(Request::parse(b"\xFE\x01\xFA"), None);
+    }
+
+    #[test]
+    fn encodes_challenge_token_as_decimal_string() {
+        assert_eq!(
+            encode_handshake(1, 9513307),
+            b"\x09\x00\x00\x00\x019513307\x00"
+        );
+
+        let tokens = ChallengeTokens::default();
+        let addr = "127.0.0.1:25565".parse().unwrap();
+        let token = tokens.issue(addr);
+        assert!(0 <= token);
+        assert!(tokens.verify(addr, token));
+        assert!(!tokens.verify("127.0.0.1:25566".parse().unwrap(), token));
+    }
+
+    #[test]
+    fn encodes_full_stat_with_players() {
+        let packet = full_stat().encode(1);
+
+        assert!(packet.starts_with(b"\x00\x00\x00\x00\x01splitnum\x00\x80\x00hostname\x00"));
+        assert!(packet.ends_with(b"\x00\x00\x01player_\x00\x00Steve\x00Alex\x00\x00"));
+    }
+
+    #[tokio::test]
+    async fn reads_full_stat() {
+        let port = responder(full_stat()).await;
+        let mut query = Query::connect("127.0.0.1", port).await.unwrap();
+
+        let stat = query.full_stat().await.unwrap();
+        assert_eq!(stat.players, ["Steve", "Alex"]);
+        assert_eq!(stat.num_players, 2);
+        assert_eq!(stat.host_port, 25565);
+        assert_eq!(
+            stat.plugin_list(),
+            (
+                Some("Paper on 1.21.1"),
+                vec!["WorldEdit 7.3", "LuckPerms 5.4"]
+            )
+        );
+
+        let basic = query.basic_stat().await.unwrap();
+        assert_eq!(basic.motd, "A Minecraft Server");
+        assert_eq!(basic.host_port, 25565);
+    }
+
+    #[tokio::test]
+    async fn reads_full_stat_without_players() {
+        let stat = FullStat {
+            players: vec![],
+            num_players: 0,
+            ..full_stat()
+        };
+        let port = responder(stat).await;
+        let mut query = Query::connect("[127.0.0.1]", port).await.unwrap();
+
+        assert!(query.full_stat().await.unwrap().players.is_empty());
+    }
+}
@@ -3,29 +3,374 @@ use serde::{Deserialize, Serialize};
 // https://minecraft.wiki/w/Raw_JSON_text_format
 // https://wiki.vg/Text_formatting#Text_components
 
-#[derive(Serialize, Deserialize, Debug)]
+mod ansi;
+mod color;
+mod event;
+mod formatting;
+
+pub use ansi::ColorDepth;
+pub use color::Color;
+pub use event::{ClickEvent, HoverEntity, HoverEvent, HoverItem};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum RawJsonText {
     String(String),
-    Object(Object),
+    /// 先頭の要素が親になり、残りはその `extra` として扱われる
+    Array(Vec<RawJsonText>),
+    Object(Box<Object>),
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+/// 内容 (`text`, `translate`, `score`, `selector`, `keybind`, `nbt` のどれか) と装飾
+///
+/// 組み立てには [`Object::text`] などから始めるビルダーを使う。
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Object {
-    pub text: String,
-
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub r#type: Option<String>,
+
+    /* 内容 */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub with: Option<Vec<RawJsonText>>,
+    /// 翻訳キーがクライアントに無かったときの表示
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selector: Option<String>,
+    /// selector や nbt が複数に一致したときの区切り
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<Box<RawJsonText>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keybind: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpret: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extra: Option<Vec<RawJsonText>>,
 
     /* 装飾 */
-    pub color: Option<String>,
+    /// 読めない色は無視する
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "color::deserialize_lenient"
+    )]
+    pub color: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub font: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub bold: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub italic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub underlined: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub strikethrough: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub obfuscated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub insertion: Option<String>,
-    // clickEvent
-    // hoverEvent
+    #[serde(rename = "clickEvent", skip_serializing_if = "Option::is_none")]
+    pub click_event: Option<ClickEvent>,
+    #[serde(rename = "hoverEvent", skip_serializing_if = "Option::is_none")]
+    pub hover_event: Option<HoverEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Score {
+    /// プレイヤー名かセレクタ
+    pub name: String,
+    pub objective: String,
+    /// 古いバージョンではサーバが値を埋めて送る
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl RawJsonText {
+    /// 装飾を除いた文字列。クライアント側で解決される内容はキーなどで代用する。
+    pub fn to_plain(&self) -> String {
+        match self {
+            RawJsonText::String(s) => s.clone(),
+            RawJsonText::Array(texts) => texts.iter().map(RawJsonText::to_plain).collect(),
+            RawJsonText::Object(object) => object.to_plain(),
+        }
+    }
+}
+
+impl From<&str> for RawJsonText {
+    fn from(s: &str) -> Self {
+        RawJsonText::String(s.to_string())
+    }
+}
+
+impl From<String> for RawJsonText {
+    fn from(s: String) -> Self {
+        RawJsonText::String(s)
+    }
+}
+
+impl From<Object> for RawJsonText {
+    fn from(object: Object) -> Self {
+        RawJsonText::Object(Box::new(object))
+    }
+}
+
+impl Object {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn translate(key: impl Into<String>, with: Vec<RawJsonText>) -> Self {
+        Self {
+            translate: Some(key.into()),
+            with: (!with.is_empty()).then_some(with),
+            ..Default::default()
+        }
+    }
+
+    pub fn score(name: impl Into<String>, objective: impl Into<String>) -> Self {
+        Self {
+            score: Some(Score {
+                name: name.into(),
+                objective: objective.into(),
+                value: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub fn selector(selector: impl Into<String>) -> Self {
+        Self {
+            selector: Some(selector.into()),
+            ..Default::default()
+        }
+    }
+
+    /// `key.jump` など。クライアントの操作設定に置き換わる。
+    pub fn keybind(key: impl Into<String>) -> Self {
+        Self {
+            keybind: Some(key.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn font(mut self, font: impl Into<String>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    pub fn bold(mut self, bold: bool) -> Self {
+        self.bold = Some(bold);
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> Self {
+        self.italic = Some(italic);
+        self
+    }
+
+    pub fn underlined(mut self, underlined: bool) -> Self {
+        self.underlined = Some(underlined);
+        self
+    }
+
+    pub fn strikethrough(mut self, strikethrough: bool) -> Self {
+        self.strikethrough = Some(strikethrough);
+        self
+    }
+
+    pub fn obfuscated(mut self, obfuscated: bool) -> Self {
+        self.obfuscated = Some(obfuscated);
+        self
+    }
+
+    pub fn fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    pub fn insertion(mut self, insertion: impl Into<String>) -> Self {
+        self.insertion = Some(insertion.into());
+        self
+    }
+
+    pub fn click(mut self, event: ClickEvent) -> Self {
+        self.click_event = Some(event);
+        self
+    }
+
+    pub fn hover(mut self, event: HoverEvent) -> Self {
+        self.hover_event = Some(event);
+        self
+    }
+
+    /// 子を追加する。子は親の装飾を引き継ぐ。
+    pub fn extra(mut self, child: impl Into<RawJsonText>) -> Self {
+        self.extra.get_or_insert_with(Vec::new).push(child.into());
+        self
+    }
+
+    fn to_plain(&self) -> String {
+        let mut s = self.content();
+        for extra in self.extra.iter().flatten() {
+            s.push_str(&extra.to_plain());
+        }
+
+        s
+    }
+
+    /// 子を除いた、この要素自体の内容
+    fn content(&self) -> String {
+        if let Some(text) = &self.text {
+            text.clone()
+        } else if let Some(key) = &self.translate {
+            self.fallback.clone().unwrap_or_else(|| key.clone())
+        } else if let Some(score) = &self.score {
+            score.value.clone().unwrap_or_default()
+        } else if let Some(selector) = &self.selector {
+            selector.clone()
+        } else if let Some(key) = &self.keybind {
+            key.clone()
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::minecraft::packet::status_response::StatusResponse;
+
+    /// 実際のサーバが返してきた説明文。読んで書き戻すと元の JSON に戻る。
+    const FIXTURES: &[&str] = &[
+        // バニラ
+        r#""A Minecraft Server""#,
+        r#"{"text":"A Minecraft Server"}"#,
+        // Spigot / Paper の motd
+        r##"{"extra":[{"bold":true,"color":"gold","text":"Example "},{"color":"#55FFFF","text":"Network"},{"text":"\n"},{"italic":true,"color":"gray","text":"[1.8-1.21]"}],"text":""}"##,
+        // 書式コードのまま
+        r#""§a§lHello§r §7world""#,
+        r#"[{"text":"a","color":"red"},"b",{"translate":"chat.type.text","with":["x",{"text":"y"}]}]"#,
+        // 1.16 より前の hoverEvent
+        r#"{"text":"x","hoverEvent":{"action":"show_text","value":"x"}}"#,
+        r#"{"text":"x","hoverEvent":{"action":"show_text","value":[{"text":"a","color":"red"}]}}"#,
+        r#"{"text":"x","hoverEvent":{"action":"show_text","contents":{"text":"y","bold":true}}}"#,
+        r#"{"text":"x","hoverEvent":{"action":"show_item","contents":{"id":"minecraft:stone","count":2}}}"#,
+        r#"{"text":"x","clickEvent":{"action":"open_url","value":"https://example.com"}}"#,
+        // 知らない action
+        r#"{"text":"x","clickEvent":{"action":"show_dialog","dialog":"minecraft:server_links"}}"#,
+        r#"{"text":"x","hoverEvent":{"action":"show_achievement","value":"achievement.openInventory"}}"#,
+    ];
+
+    #[test]
+    fn round_trips_fixtures() {
+        for fixture in FIXTURES {
+            let text: RawJsonText = serde_json::from_str(fixture)
+                .unwrap_or_else(|e| panic!("failed to read {}: {}", fixture, e));
+            let expected: Value = serde_json::from_str(fixture).unwrap();
+            assert_eq!(
+                serde_json::to_value(&text).unwrap(),
+                expected,
+                "{}",
+                fixture
+            );
+        }
+    }
+
+    #[test]
+    fn reads_known_events() {
+        let text: RawJsonText = serde_json::from_str(
+            r#"{"text":"x","clickEvent":{"action":"change_page","value":"3"},"hoverEvent":{"action":"show_text","contents":"y"}}"#,
+        )
+        .unwrap();
+        let RawJsonText::Object(object) = text else {
+            panic!("expected an object");
+        };
+        assert_eq!(object.click_event, Some(ClickEvent::ChangePage(3)));
+        assert_eq!(
+            object.hover_event,
+            Some(HoverEvent::ShowText(Box::new("y".into())))
+        );
+    }
+
+    #[test]
+    fn tolerates_odd_colors() {
+        // バニラと同じく短い 16 進数も受け付ける
+        let text: RawJsonText = serde_json::from_str(r##"{"text":"x","color":"#fff"}"##).unwrap();
+        assert_eq!(text, Object::text("x").color(Color::Hex(0xFFF)).into());
+
+        let text: RawJsonText = serde_json::from_str(r#"{"text":"x","color":"pink"}"#).unwrap();
+        assert_eq!(text, Object::text("x").into());
+    }
+
+    #[test]
+    fn reads_status_with_legacy_events() {
+        let status = json!({
+            "version": {"name": "Spigot 1.12.2", "protocol": 340},
+            "players": {"max": 100, "online": 3},
+            "description": {
+                "text": "",
+                "extra": [
+                    {"text": "x", "color": "#fff"},
+                    {"text": "y", "hoverEvent": {"action": "show_text", "value": "x"}},
+                    {"text": "z", "clickEvent": {"action": "show_dialog", "dialog": "a"}},
+                ],
+            },
+        });
+        let status: StatusResponse = serde_json::from_value(status).unwrap();
+        assert_eq!(status.description.to_plain(), "xyz");
+    }
+
+    #[test]
+    fn builds_components() {
+        let text: RawJsonText = Object::text("Hello")
+            .color(Color::Gold)
+            .bold(true)
+            .extra(Object::keybind("key.jump"))
+            .click(ClickEvent::RunCommand("/spawn".to_string()))
+            .into();
+        assert_eq!(
+            serde_json::to_value(&text).unwrap(),
+            json!({
+                "text": "Hello",
+                "color": "gold",
+                "bold": true,
+                "extra": [{"keybind": "key.jump"}],
+                "clickEvent": {"action": "run_command", "value": "/spawn"},
+            })
+        );
+        assert_eq!(text.to_plain(), "Hellokey.jump");
+    }
 }
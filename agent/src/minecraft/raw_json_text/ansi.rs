@@ -0,0 +1,87 @@
+//! 端末向けの ANSI エスケープシーケンスでの表示
+
+use std::fmt::Write;
+
+use super::{
+    formatting::{walk, Style},
+    RawJsonText,
+};
+
+/// 端末が表示できる色の数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+}
+
+impl ColorDepth {
+    /// `COLORTERM` が `truecolor` か `24bit` なら TrueColor
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM").as_deref() {
+            Ok("truecolor" | "24bit") => ColorDepth::TrueColor,
+            _ => ColorDepth::Ansi256,
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+impl RawJsonText {
+    pub fn to_ansi(&self, depth: ColorDepth) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+
+        walk(self, &Style::default(), &mut |style, s| {
+            if s.is_empty() {
+                return;
+            }
+
+            if *style != current {
+                out.push_str(RESET);
+                write_sgr(&mut out, style, depth);
+                current = style.clone();
+            }
+            out.push_str(s);
+        });
+
+        if current != Style::default() {
+            out.push_str(RESET);
+        }
+
+        out
+    }
+}
+
+fn write_sgr(out: &mut String, style: &Style, depth: ColorDepth) {
+    let mut params = vec![];
+    if style.bold {
+        params.push("1".to_string());
+    }
+    if style.italic {
+        params.push("3".to_string());
+    }
+    if style.underlined {
+        params.push("4".to_string());
+    }
+    if style.strikethrough {
+        params.push("9".to_string());
+    }
+
+    if let Some(rgb) = style.color.and_then(|color| color.to_rgb()) {
+        let [_, r, g, b] = rgb.to_be_bytes();
+        params.push(match depth {
+            ColorDepth::TrueColor => format!("38;2;{};{};{}", r, g, b),
+            ColorDepth::Ansi256 => format!("38;5;{}", to_ansi256(r, g, b)),
+        });
+    }
+
+    if !params.is_empty() {
+        let _ = write!(out, "\x1b[{}m", params.join(";"));
+    }
+}
+
+/// 6x6x6 のカラーキューブで一番近い色
+fn to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
@@ -0,0 +1,164 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// 名前付きの 16 色か `#RRGGBB`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+    /// 親の色を打ち消す
+    Reset,
+    Hex(u32),
+}
+
+const NAMES: [(Color, &str); 17] = [
+    (Color::Black, "black"),
+    (Color::DarkBlue, "dark_blue"),
+    (Color::DarkGreen, "dark_green"),
+    (Color::DarkAqua, "dark_aqua"),
+    (Color::DarkRed, "dark_red"),
+    (Color::DarkPurple, "dark_purple"),
+    (Color::Gold, "gold"),
+    (Color::Gray, "gray"),
+    (Color::DarkGray, "dark_gray"),
+    (Color::Blue, "blue"),
+    (Color::Green, "green"),
+    (Color::Aqua, "aqua"),
+    (Color::Red, "red"),
+    (Color::LightPurple, "light_purple"),
+    (Color::Yellow, "yellow"),
+    (Color::White, "white"),
+    (Color::Reset, "reset"),
+];
+
+/// `§0` から `§f` の色と、その RGB
+const CODES: [(Color, char, u32); 16] = [
+    (Color::Black, '0', 0x000000),
+    (Color::DarkBlue, '1', 0x0000AA),
+    (Color::DarkGreen, '2', 0x00AA00),
+    (Color::DarkAqua, '3', 0x00AAAA),
+    (Color::DarkRed, '4', 0xAA0000),
+    (Color::DarkPurple, '5', 0xAA00AA),
+    (Color::Gold, '6', 0xFFAA00),
+    (Color::Gray, '7', 0xAAAAAA),
+    (Color::DarkGray, '8', 0x555555),
+    (Color::Blue, '9', 0x5555FF),
+    (Color::Green, 'a', 0x55FF55),
+    (Color::Aqua, 'b', 0x55FFFF),
+    (Color::Red, 'c', 0xFF5555),
+    (Color::LightPurple, 'd', 0xFF55FF),
+    (Color::Yellow, 'e', 0xFFFF55),
+    (Color::White, 'f', 0xFFFFFF),
+];
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color::Hex(u32::from_be_bytes([0, r, g, b]))
+    }
+
+    pub fn from_code(code: char) -> Option<Self> {
+        let code = code.to_ascii_lowercase();
+        CODES
+            .iter()
+            .find(|&&(_, c, _)| c == code)
+            .map(|&(color, _, _)| color)
+    }
+
+    /// 書式コードの文字。`Hex` と `Reset` には無い。
+    pub fn code(&self) -> Option<char> {
+        CODES
+            .iter()
+            .find(|(color, _, _)| color == self)
+            .map(|&(_, c, _)| c)
+    }
+
+    /// `0xRRGGBB`。`Reset` には無い。
+    pub fn to_rgb(&self) -> Option<u32> {
+        if let Color::Hex(rgb) = self {
+            return Some(*rgb);
+        }
+
+        CODES
+            .iter()
+            .find(|(color, _, _)| color == self)
+            .map(|&(_, _, rgb)| rgb)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Color::Hex(rgb) = self {
+            return write!(f, "#{:06X}", rgb);
+        }
+
+        let (_, name) = NAMES
+            .iter()
+            .find(|(color, _)| color == self)
+            .expect("every named color is listed");
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // バニラと同じく桁数は問わない (`#fff` は `#000FFF`)
+        if let Some(hex) = s.strip_prefix('#') {
+            return match u32::from_str_radix(hex, 16) {
+                Ok(rgb) if hex.chars().all(|c| c.is_ascii_hexdigit()) && rgb <= 0xFFFFFF => {
+                    Ok(Color::Hex(rgb))
+                }
+                _ => Err(format!("invalid hex color: {}", s)),
+            };
+        }
+
+        NAMES
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|&(color, _)| color)
+            .ok_or_else(|| format!("unknown color: {}", s))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_string()
+    }
+}
+
+/// 知らない色のせいで説明文全体を読めなくならないよう、読めなければ `None` にする
+pub(super) fn deserialize_lenient<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    let value = Option::<Value>::deserialize(deserializer)?;
+    Ok(value
+        .as_ref()
+        .and_then(Value::as_str)
+        .and_then(|s| s.parse().ok()))
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use super::RawJsonText;
+
+/// クリックしたときの動作 (1.21.4 までの `clickEvent` 形式)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", content = "value", rename_all = "snake_case")]
+pub enum ClickEvent {
+    OpenUrl(String),
+    OpenFile(String),
+    RunCommand(String),
+    SuggestCommand(String),
+    /// 本のページ。文字列で送ってくるサーバもある。
+    #[serde(deserialize_with = "page_number")]
+    ChangePage(i32),
+    CopyToClipboard(String),
+    /// 知らない action (1.21.5 以降の `show_dialog` など)。受け取ったまま送り返す。
+    #[serde(untagged)]
+    Other(Value),
+}
+
+fn page_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Number(n) => n
+            .as_i64()
+            .map(|n| n as i32)
+            .ok_or_else(|| serde::de::Error::custom("invalid page number")),
+        Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+        _ => Err(serde::de::Error::custom("invalid page number")),
+    }
+}
+
+/// カーソルを合わせたときの表示 (`contents` 形式)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", content = "contents", rename_all = "snake_case")]
+pub enum HoverEvent {
+    ShowText(Box<RawJsonText>),
+    ShowItem(HoverItem),
+    ShowEntity(HoverEntity),
+    /// 1.16 より前の `value` 形式や知らない action。受け取ったまま送り返す。
+    #[serde(untagged)]
+    Other(Value),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HoverItem {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i32>,
+    /// 1.20.5 以降のアイテムコンポーネント
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<Value>,
+    /// 1.20.5 より前の SNBT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HoverEntity {
+    pub r#type: String,
+    /// UUID の文字列か、4 つの整数の配列
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Box<RawJsonText>>,
+}
@@ -0,0 +1,392 @@
+//! `§a§lHello` のような書式コードとの相互変換
+//!
+//! https://minecraft.wiki/w/Formatting_codes
+
+use super::{Color, Object, RawJsonText};
+
+const SECTION: char = '§';
+
+/// 書式コードで表せる装飾
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Style {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+impl Style {
+    fn apply(&self, mut object: Object) -> Object {
+        object.color = self.color;
+        object.bold = self.bold.then_some(true);
+        object.italic = self.italic.then_some(true);
+        object.underlined = self.underlined.then_some(true);
+        object.strikethrough = self.strikethrough.then_some(true);
+        object.obfuscated = self.obfuscated.then_some(true);
+        object
+    }
+
+    /// 親の装飾に子の指定を重ねる
+    fn inherit(&self, object: &Object) -> Style {
+        Style {
+            color: match object.color {
+                Some(Color::Reset) => None,
+                Some(color) => Some(color),
+                None => self.color,
+            },
+            bold: object.bold.unwrap_or(self.bold),
+            italic: object.italic.unwrap_or(self.italic),
+            underlined: object.underlined.unwrap_or(self.underlined),
+            strikethrough: object.strikethrough.unwrap_or(self.strikethrough),
+            obfuscated: object.obfuscated.unwrap_or(self.obfuscated),
+        }
+    }
+
+    fn flags(&self) -> [(bool, char); 5] {
+        [
+            (self.obfuscated, 'k'),
+            (self.bold, 'l'),
+            (self.strikethrough, 'm'),
+            (self.underlined, 'n'),
+            (self.italic, 'o'),
+        ]
+    }
+
+    /// `current` からこの装飾に切り替えるコードを書く
+    ///
+    /// 色のコードは装飾を打ち消すので、装飾を外すときは色から書き直す。
+    fn write_codes(&self, current: &Style, out: &mut String) {
+        let only_added = self.color == current.color
+            && current
+                .flags()
+                .iter()
+                .zip(self.flags())
+                .all(|(&(before, _), (after, _))| !before || after);
+        if only_added {
+            for ((before, _), (after, code)) in current.flags().into_iter().zip(self.flags()) {
+                if after && !before {
+                    out.extend([SECTION, code]);
+                }
+            }
+            return;
+        }
+
+        match self.color {
+            Some(Color::Hex(rgb)) => {
+                out.extend([SECTION, 'x']);
+                for digit in format!("{:06x}", rgb).chars() {
+                    out.extend([SECTION, digit]);
+                }
+            }
+            Some(color) => out.extend([SECTION, color.code().unwrap_or('r')]),
+            None => out.extend([SECTION, 'r']),
+        }
+
+        for (enabled, code) in self.flags() {
+            if enabled {
+                out.extend([SECTION, code]);
+            }
+        }
+    }
+}
+
+impl RawJsonText {
+    /// `§` の書式コードを含む文字列を解釈する。知らないコードや末尾の `§` はそのまま残す。
+    ///
+    /// バニラと同じく色のコードはそれまでの装飾を打ち消すので、`§l§a` は太字にならない。
+    /// 色と装飾を併せるときは `§a§l` の順に書く。
+    pub fn from_legacy(s: &str) -> Self {
+        let mut segments = vec![];
+        let mut style = Style::default();
+        let mut text = String::new();
+
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let Some(&code) = chars.peek().filter(|_| c == SECTION) else {
+                text.push(c);
+                continue;
+            };
+
+            let next = match code.to_ascii_lowercase() {
+                'x' => match parse_hex(chars.clone().skip(1)) {
+                    Some(rgb) => {
+                        // §x に続く §R§R§G§G§B§B を読み飛ばす
+                        chars.nth(12);
+                        Style {
+                            color: Some(Color::Hex(rgb)),
+                            ..Default::default()
+                        }
+                    }
+                    None => {
+                        text.push(c);
+                        continue;
+                    }
+                },
+                'k' => Style {
+                    obfuscated: true,
+                    ..style.clone()
+                },
+                'l' => Style {
+                    bold: true,
+                    ..style.clone()
+                },
+                'm' => Style {
+                    strikethrough: true,
+                    ..style.clone()
+                },
+                'n' => Style {
+                    underlined: true,
+                    ..style.clone()
+                },
+                'o' => Style {
+                    italic: true,
+                    ..style.clone()
+                },
+                'r' => Style::default(),
+                code => match Color::from_code(code) {
+                    Some(color) => Style {
+                        color: Some(color),
+                        ..Default::default()
+                    },
+                    None => {
+                        text.push(c);
+                        continue;
+                    }
+                },
+            };
+            if !code.eq_ignore_ascii_case(&'x') {
+                chars.next();
+            }
+
+            if next != style {
+                if !text.is_empty() {
+                    segments.push((style, std::mem::take(&mut text)));
+                }
+                style = next;
+            }
+        }
+        if !text.is_empty() || segments.is_empty() {
+            segments.push((style, text));
+        }
+
+        if let [(style, text)] = segments.as_slice() {
+            if *style == Style::default() {
+                return RawJsonText::String(text.clone());
+            }
+        }
+
+        let mut root = Object::text("");
+        for (style, text) in segments {
+            root = root.extra(style.apply(Object::text(text)));
+        }
+        root.into()
+    }
+
+    /// 設定ファイル向けの `&a` や `&#RRGGBB` を `§` に置き換えてから解釈する
+    ///
+    /// `&z` や `&&`、6 桁に満たない `&#` は書式コードではないのでそのまま残す。
+    pub fn from_ampersand(s: &str) -> Self {
+        let mut legacy = String::with_capacity(s.len());
+
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '&' {
+                legacy.push(c);
+                continue;
+            }
+
+            match chars.peek() {
+                Some('#') => {
+                    let hex: String = chars.clone().skip(1).take(6).collect();
+                    if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        legacy.extend([SECTION, 'x']);
+                        for digit in hex.chars() {
+                            legacy.extend([SECTION, digit]);
+                        }
+                        chars.nth(6);
+                    } else {
+                        legacy.push(c);
+                    }
+                }
+                Some(&code) if is_code(code) => {
+                    legacy.push(SECTION);
+                }
+                _ => legacy.push(c),
+            }
+        }
+
+        Self::from_legacy(&legacy)
+    }
+
+    /// `§` の書式コードを使った文字列にする。イベントやフォントなどは失われる。
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        walk(self, &Style::default(), &mut |style, s| {
+            if s.is_empty() {
+                return;
+            }
+
+            if *style != current {
+                style.write_codes(&current, &mut out);
+                current = style.clone();
+            }
+            out.push_str(s);
+        });
+        out
+    }
+}
+
+fn is_code(c: char) -> bool {
+    matches!(c.to_ascii_lowercase(), '0'..='9' | 'a'..='f' | 'k'..='o' | 'r' | 'x')
+}
+
+/// `§R§R§G§G§B§B`
+fn parse_hex(chars: impl Iterator<Item = char>) -> Option<u32> {
+    let chars: Vec<char> = chars.take(12).collect();
+    if chars.len() != 12 {
+        return None;
+    }
+
+    let mut hex = String::new();
+    for pair in chars.chunks(2) {
+        if pair[0] != SECTION || !pair[1].is_ascii_hexdigit() {
+            return None;
+        }
+        hex.push(pair[1]);
+    }
+
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// 装飾を親から引き継ぎながら、内容のある部分を順に渡す
+pub(super) fn walk(text: &RawJsonText, parent: &Style, f: &mut impl FnMut(&Style, &str)) {
+    match text {
+        RawJsonText::String(s) => f(parent, s),
+        RawJsonText::Array(texts) => {
+            // 先頭の要素が残りの親になる
+            let Some((first, rest)) = texts.split_first() else {
+                return;
+            };
+            walk(first, parent, f);
+
+            let style = match first {
+                RawJsonText::Object(object) => parent.inherit(object),
+                _ => parent.clone(),
+            };
+            for text in rest {
+                walk(text, &style, f);
+            }
+        }
+        RawJsonText::Object(object) => {
+            let style = parent.inherit(object);
+            f(&style, &object.content());
+            for extra in object.extra.iter().flatten() {
+                walk(extra, &style, f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一つだけの装飾付きの部分
+    fn styled(text: &str, style: impl FnOnce(Object) -> Object) -> RawJsonText {
+        Object::text("").extra(style(Object::text(text))).into()
+    }
+
+    #[test]
+    fn reads_legacy_codes() {
+        let cases = [
+            ("plain", RawJsonText::from("plain")),
+            ("§aHi", styled("Hi", |o| o.color(Color::Green))),
+            ("§a§lHi", styled("Hi", |o| o.color(Color::Green).bold(true))),
+            ("§A§LHi", styled("Hi", |o| o.color(Color::Green).bold(true))),
+            // 色のコードが装飾を打ち消す
+            ("§l§aHi", styled("Hi", |o| o.color(Color::Green))),
+            (
+                "§x§f§f§0§0§0§0Hi",
+                styled("Hi", |o| o.color(Color::Hex(0xFF0000))),
+            ),
+            (
+                "§aa§rb",
+                Object::text("")
+                    .extra(Object::text("a").color(Color::Green))
+                    .extra(Object::text("b"))
+                    .into(),
+            ),
+            // 知らないコードと末尾の §
+            ("§zHi", RawJsonText::from("§zHi")),
+            ("Hi§", RawJsonText::from("Hi§")),
+            ("§aHi§", styled("Hi§", |o| o.color(Color::Green))),
+        ];
+
+        for (legacy, expected) in cases {
+            assert_eq!(RawJsonText::from_legacy(legacy), expected, "{}", legacy);
+        }
+    }
+
+    #[test]
+    fn reads_ampersand_codes() {
+        let cases = [
+            ("&aHi", styled("Hi", |o| o.color(Color::Green))),
+            (
+                "&#FF0000Hi",
+                styled("Hi", |o| o.color(Color::Hex(0xFF0000))),
+            ),
+            ("&zHi", RawJsonText::from("&zHi")),
+            ("a && b", RawJsonText::from("a && b")),
+            ("&#12345Hi", RawJsonText::from("&#12345Hi")),
+            ("&#GG0000Hi", RawJsonText::from("&#GG0000Hi")),
+            ("Hi&", RawJsonText::from("Hi&")),
+        ];
+
+        for (config, expected) in cases {
+            assert_eq!(RawJsonText::from_ampersand(config), expected, "{}", config);
+        }
+    }
+
+    #[test]
+    fn writes_legacy_codes() {
+        let cases: [(RawJsonText, &str); 5] = [
+            ("plain".into(), "plain"),
+            (styled("Hi", |o| o.color(Color::Green).bold(true)), "§a§lHi"),
+            (
+                styled("Hi", |o| o.color(Color::Hex(0xFF0000))),
+                "§x§f§f§0§0§0§0Hi",
+            ),
+            // 装飾を外すときは色から書き直す
+            (
+                Object::text("a")
+                    .color(Color::Gold)
+                    .bold(true)
+                    .extra(Object::text("b").bold(false))
+                    .into(),
+                "§6§la§6b",
+            ),
+            // 子は親の装飾を引き継ぐ
+            (
+                Object::text("a")
+                    .color(Color::Red)
+                    .extra(Object::text("b").italic(true))
+                    .extra(Object::text("c").color(Color::Reset))
+                    .into(),
+                "§ca§ob§rc",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(text.to_legacy(), expected);
+            assert_eq!(
+                RawJsonText::from_legacy(expected).to_legacy(),
+                expected,
+                "{}",
+                expected
+            );
+        }
+    }
+}
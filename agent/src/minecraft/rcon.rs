@@ -0,0 +1,211 @@
+//! サーバのコマンドを実行する RCON クライアント
+//!
+//! https://minecraft.wiki/w/RCON
+//!
+//! 整数はリトルエンディアン。パケットは長さ、リクエスト ID、種類、
+//! NUL 終端の本文と、最後にもう 1 バイトの NUL からなる。
+
+use std::{io, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+
+use super::{connection::connect, Error, Result};
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake;
+
+pub const DEFAULT_PORT: u16 = 25575;
+
+const LOGIN: i32 = 3;
+const COMMAND: i32 = 2;
+const RESPONSE: i32 = 0;
+
+/// リクエスト ID、種類、本文末尾の NUL 2 つ
+const HEADER_LEN: usize = 10;
+/// サーバからの 1 パケットの本文は 4096 バイトまで
+const MAX_RESPONSE_LEN: usize = 4096;
+/// これより長いコマンドはサーバに切断される
+pub const MAX_COMMAND_LEN: usize = 1446;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    payload: Vec<u8>,
+}
+
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Rcon {
+    /// 接続してログインまで行う
+    pub async fn connect(host: &str, port: u16, password: &str) -> Result<Self> {
+        let stream = connect(host, port).await?;
+        let mut rcon = Self { stream, next_id: 1 };
+        time::timeout(TIMEOUT, rcon.login(password)).await??;
+
+        Ok(rcon)
+    }
+
+    async fn login(&mut self, password: &str) -> Result<()> {
+        let id = self.send(LOGIN, password).await?;
+
+        loop {
+            let packet = match self.read().await {
+                Ok(packet) => packet,
+                // 応答を返さずに切断するサーバもある
+                Err(Error::Disconnected(_)) => return Err(Error::AuthenticationFailed),
+                Err(e) => return Err(e),
+            };
+            // 失敗すると ID が -1 になる
+            if packet.id == -1 {
+                return Err(Error::AuthenticationFailed);
+            }
+            // ログインの応答の前に空の RESPONSE を送ってくる実装もある
+            if packet.id == id && packet.kind == COMMAND {
+                return Ok(());
+            }
+        }
+    }
+
+    /// コマンドを実行して出力を返す。先頭の `/` はあってもなくてもよい。
+    ///
+    /// 長い出力は複数のパケットに分かれて届くが、終わりを示すものは無い。
+    /// そこでコマンドの直後に無効なパケットを送り、その応答が届くまでを出力とする。
+    /// 出力は `§` の書式コードを含むことがある。
+    pub async fn command(&mut self, command: &str) -> Result<String> {
+        let command = command.strip_prefix('/').unwrap_or(command);
+        if MAX_COMMAND_LEN < command.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "command too long: {} bytes (max {})",
+                    command.len(),
+                    MAX_COMMAND_LEN
+                ),
+            )
+            .into());
+        }
+
+        time::timeout(TIMEOUT, async {
+            let id = self.send(COMMAND, command).await?;
+            let end = self.send(RESPONSE, "").await?;
+
+            let mut output = vec![];
+            let mut received = false;
+            loop {
+                let packet = match self.read().await {
+                    Ok(packet) => packet,
+                    // `stop` などは応答を返してすぐに切断する
+                    Err(e) if received && e.is_unreachable() => break,
+                    Err(e) => return Err(e),
+                };
+                if packet.id == end {
+                    break;
+                }
+                if packet.id == id && packet.kind == RESPONSE {
+                    output.extend(packet.payload);
+                    received = true;
+                }
+            }
+
+            // 分割はバイト単位なので、つなげてから文字列にする
+            Ok(String::from_utf8_lossy(&output).into_owned())
+        })
+        .await?
+    }
+
+    async fn send(&mut self, kind: i32, payload: &str) -> Result<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let mut buf = Vec::with_capacity(payload.len() + HEADER_LEN + 4);
+        buf.extend(((payload.len() + HEADER_LEN) as i32).to_le_bytes());
+        buf.extend(id.to_le_bytes());
+        buf.extend(kind.to_le_bytes());
+        buf.extend(payload.as_bytes());
+        buf.extend([0, 0]);
+        self.stream.write_all(&buf).await?;
+
+        Ok(id)
+    }
+
+    async fn read(&mut self) -> Result<Packet> {
+        let len = match self.stream.read_i32_le().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(Error::Disconnected("connection closed".to_string()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if HEADER_LEN + MAX_RESPONSE_LEN < len {
+            return Err(Error::FrameTooLarge(len));
+        }
+        if len < HEADER_LEN {
+            return Err(Error::protocol(format!(
+                "RCON packet too short: {} bytes",
+                len
+            )));
+        }
+
+        let id = self.stream.read_i32_le().await?;
+        let kind = self.stream.read_i32_le().await?;
+        let mut payload = vec![0; len - 8];
+        self.stream.read_exact(&mut payload).await?;
+        // 末尾の NUL 2 つ
+        payload.truncate(len - HEADER_LEN);
+
+        Ok(Packet { id, kind, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fake::FakeRcon, *};
+
+    #[tokio::test]
+    async fn wrong_password_fails_to_log_in() {
+        let server = FakeRcon::start("password").await;
+
+        let result = Rcon::connect("127.0.0.1", server.port(), "wrong").await;
+        assert!(matches!(result, Err(Error::AuthenticationFailed)));
+        assert!(server.commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn joins_output_split_over_packets() {
+        let server = FakeRcon::start("password").await;
+        let mut rcon = Rcon::connect("127.0.0.1", server.port(), "password")
+            .await
+            .unwrap();
+
+        // 4096 バイトの境目で切れた文字も元に戻る
+        assert_eq!(rcon.command("/help").await.unwrap(), fake::help_output());
+        // 続くコマンドに前の出力が混ざらない
+        assert_eq!(rcon.command("list").await.unwrap(), "");
+        assert_eq!(server.commands(), ["help", "list"]);
+    }
+
+    #[tokio::test]
+    async fn too_long_command_keeps_connection() {
+        let server = FakeRcon::start("password").await;
+        let mut rcon = Rcon::connect("127.0.0.1", server.port(), "password")
+            .await
+            .unwrap();
+
+        let e = rcon
+            .command(&"a".repeat(MAX_COMMAND_LEN + 1))
+            .await
+            .unwrap_err();
+        assert!(!e.is_unreachable());
+        assert_eq!(rcon.command("list").await.unwrap(), "");
+        assert_eq!(server.commands(), ["list"]);
+    }
+}
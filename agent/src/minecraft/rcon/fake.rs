@@ -0,0 +1,116 @@
+//! テスト用の RCON サーバ
+//!
+//! バニラと同じく、ログインに失敗すると ID -1 で応答し、長い出力は 4096 バイトごとの
+//! パケットに分けて送る。`test-util` フィーチャで有効になる。
+
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use super::{COMMAND, HEADER_LEN, LOGIN, MAX_RESPONSE_LEN, RESPONSE};
+
+pub struct FakeRcon {
+    port: u16,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeRcon {
+    /// 空いているポートで待ち受ける
+    pub async fn start(password: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let commands = Arc::new(Mutex::new(vec![]));
+
+        let password = password.to_string();
+        let received = Arc::clone(&commands);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, password.clone(), Arc::clone(&received)));
+            }
+        });
+
+        Self { port, commands }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// ログイン後に受け取ったコマンド
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+/// `help` の出力。3 つのパケットに分かれ、境目は `あ` の途中に来る。
+/// それ以外のコマンドの出力は空。
+pub fn help_output() -> String {
+    "あ".repeat(3000)
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    password: String,
+    commands: Arc<Mutex<Vec<String>>>,
+) -> io::Result<()> {
+    let mut authenticated = false;
+
+    loop {
+        let (id, kind, payload) = read(&mut stream).await?;
+        match kind {
+            LOGIN => {
+                authenticated = payload == password;
+                let id = if authenticated { id } else { -1 };
+                write(&mut stream, id, COMMAND, b"").await?;
+            }
+            COMMAND if authenticated => {
+                let output = if payload == "help" {
+                    help_output()
+                } else {
+                    String::new()
+                };
+                commands.lock().unwrap().push(payload);
+
+                for chunk in output.as_bytes().chunks(MAX_RESPONSE_LEN) {
+                    write(&mut stream, id, RESPONSE, chunk).await?;
+                }
+                if output.is_empty() {
+                    write(&mut stream, id, RESPONSE, b"").await?;
+                }
+            }
+            // バニラはログインせずにコマンドを送ると切断する
+            COMMAND => return Ok(()),
+            kind => {
+                let message = format!("Unknown request {:x}", kind);
+                write(&mut stream, id, RESPONSE, message.as_bytes()).await?;
+            }
+        }
+    }
+}
+
+async fn read(stream: &mut TcpStream) -> io::Result<(i32, i32, String)> {
+    let len = stream.read_i32_le().await? as usize;
+    let id = stream.read_i32_le().await?;
+    let kind = stream.read_i32_le().await?;
+    let mut payload = vec![0; len.saturating_sub(8)];
+    stream.read_exact(&mut payload).await?;
+    payload.truncate(len.saturating_sub(HEADER_LEN));
+
+    Ok((id, kind, String::from_utf8_lossy(&payload).into_owned()))
+}
+
+async fn write(stream: &mut TcpStream, id: i32, kind: i32, payload: &[u8]) -> io::Result<()> {
+    let mut buf = vec![];
+    buf.extend(((payload.len() + HEADER_LEN) as i32).to_le_bytes());
+    buf.extend(id.to_le_bytes());
+    buf.extend(kind.to_le_bytes());
+    buf.extend(payload);
+    buf.extend([0, 0]);
+    stream.write_all(&buf).await
+}
@@ -0,0 +1,158 @@
+//! リリース名とプロトコル番号の対応 (1.7 以降の正式リリースのみ)
+//!
+//! https://minecraft.wiki/w/Protocol_version_numbers
+
+/// 知っている中で最も新しいプロトコル
+pub const LATEST: i32 = 772;
+
+/* ログイン関連のパケットの形式が変わったバージョン */
+pub const V1_16: i32 = 735;
+pub const V1_19: i32 = 759;
+pub const V1_19_1: i32 = 760;
+pub const V1_19_3: i32 = 761;
+pub const V1_20_2: i32 = 764;
+pub const V1_20_5: i32 = 766;
+pub const V1_21: i32 = 767;
+pub const V1_21_2: i32 = 768;
+
+/// 古い順
+const RELEASES: &[(&str, i32)] = &[
+    ("1.7.2", 4),
+    ("1.7.4", 4),
+    ("1.7.5", 4),
+    ("1.7.6", 5),
+    ("1.7.7", 5),
+    ("1.7.8", 5),
+    ("1.7.9", 5),
+    ("1.7.10", 5),
+    ("1.8", 47),
+    ("1.8.1", 47),
+    ("1.8.2", 47),
+    ("1.8.3", 47),
+    ("1.8.4", 47),
+    ("1.8.5", 47),
+    ("1.8.6", 47),
+    ("1.8.7", 47),
+    ("1.8.8", 47),
+    ("1.8.9", 47),
+    ("1.9", 107),
+    ("1.9.1", 108),
+    ("1.9.2", 109),
+    ("1.9.3", 110),
+    ("1.9.4", 110),
+    ("1.10", 210),
+    ("1.10.1", 210),
+    ("1.10.2", 210),
+    ("1.11", 315),
+    ("1.11.1", 316),
+    ("1.11.2", 316),
+    ("1.12", 335),
+    ("1.12.1", 338),
+    ("1.12.2", 340),
+    ("1.13", 393),
+    ("1.13.1", 401),
+    ("1.13.2", 404),
+    ("1.14", 477),
+    ("1.14.1", 480),
+    ("1.14.2", 485),
+    ("1.14.3", 490),
+    ("1.14.4", 498),
+    ("1.15", 573),
+    ("1.15.1", 575),
+    ("1.15.2", 578),
+    ("1.16", 735),
+    ("1.16.1", 736),
+    ("1.16.2", 751),
+    ("1.16.3", 753),
+    ("1.16.4", 754),
+    ("1.16.5", 754),
+    ("1.17", 755),
+    ("1.17.1", 756),
+    ("1.18", 757),
+    ("1.18.1", 757),
+    ("1.18.2", 758),
+    ("1.19", 759),
+    ("1.19.1", 760),
+    ("1.19.2", 760),
+    ("1.19.3", 761),
+    ("1.19.4", 762),
+    ("1.20", 763),
+    ("1.20.1", 763),
+    ("1.20.2", 764),
+    ("1.20.3", 765),
+    ("1.20.4", 765),
+    ("1.20.5", 766),
+    ("1.20.6", 766),
+    ("1.21", 767),
+    ("1.21.1", 767),
+    ("1.21.2", 768),
+    ("1.21.3", 768),
+    ("1.21.4", 769),
+    ("1.21.5", 770),
+    ("1.21.6", 771),
+    ("1.21.7", 772),
+    ("1.21.8", 772),
+];
+
+/// `1.21.1` → 767
+pub fn protocol(name: &str) -> Option<i32> {
+    RELEASES
+        .iter()
+        .find(|(release, _)| *release == name)
+        .map(|&(_, protocol)| protocol)
+}
+
+/// 767 → `["1.21", "1.21.1"]`
+pub fn releases(protocol: i32) -> Vec<&'static str> {
+    RELEASES
+        .iter()
+        .filter(|&&(_, p)| p == protocol)
+        .map(|&(release, _)| release)
+        .collect()
+}
+
+/// 767 → `1.21-1.21.1`。知らない番号なら `None`。
+pub fn name(protocol: i32) -> Option<String> {
+    match releases(protocol).as_slice() {
+        [] => None,
+        [release] => Some(release.to_string()),
+        [first, .., last] => Some(format!("{}-{}", first, last)),
+    }
+}
+
+/// リリース名でもプロトコル番号でも受け付ける
+pub fn parse(s: &str) -> Option<i32> {
+    s.parse().ok().or_else(|| protocol(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_release_names_and_numbers() {
+        assert_eq!(parse("1.21.1"), Some(767));
+        assert_eq!(parse("1.8.9"), Some(47));
+        assert_eq!(parse("767"), Some(767));
+        // 知らない番号もそのまま使える
+        assert_eq!(parse("9999"), Some(9999));
+        assert_eq!(parse("1.21.99"), None);
+        assert_eq!(parse("latest"), None);
+    }
+
+    #[test]
+    fn names_protocols() {
+        assert_eq!(name(767).as_deref(), Some("1.21-1.21.1"));
+        assert_eq!(name(47).as_deref(), Some("1.8-1.8.9"));
+        assert_eq!(name(761).as_deref(), Some("1.19.3"));
+        assert_eq!(name(LATEST).as_deref(), Some("1.21.7-1.21.8"));
+        assert_eq!(name(10_000), None);
+        assert_eq!(releases(760), ["1.19.1", "1.19.2"]);
+    }
+
+    #[test]
+    fn releases_are_in_order() {
+        assert!(RELEASES.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert_eq!(RELEASES.last().unwrap().1, LATEST);
+    }
+}